@@ -0,0 +1,43 @@
+//! Acknowledgements bound to the channel a delivery arrived on
+
+use amqp::{Basic, Channel};
+use error::BenderMqError;
+use GenResult;
+
+
+/// Settles a single delivery on the channel it arrived on. Settling consumes \
+/// the handle, so a delivery can't be acknowledged twice, and the handle \
+/// can't outlive the callback it was handed to. A delivery whose handle is \
+/// dropped without settling stays unacknowledged until the channel closes, \
+/// then the broker delivers it again.
+pub struct AckHandle<'a>{
+    channel: &'a mut Channel,
+    delivery_tag: u64
+}
+
+
+impl<'a> AckHandle<'a>{
+    pub(crate) fn new(channel: &'a mut Channel, delivery_tag: u64) -> Self{
+        AckHandle{ channel, delivery_tag }
+    }
+
+    /// The tag of the delivery this handle settles
+    pub fn delivery_tag(&self) -> u64{
+        self.delivery_tag
+    }
+
+    /// Acknowledge the delivery
+    pub fn ack(self) -> GenResult<()>{
+        // delivery_tag, multiple
+        self.channel.basic_ack(self.delivery_tag, false).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Reject the delivery. With `requeue` set it goes back into its queue, \
+    /// otherwise it is dropped or dead-lettered
+    pub fn nack(self, requeue: bool) -> GenResult<()>{
+        // delivery_tag, multiple, requeue
+        self.channel.basic_nack(self.delivery_tag, false, requeue).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Controls how long bender_mq waits between attempts to (re)connect to the \
+//! broker. The delay starts at `initial`, grows by `multiplier` with every \
+//! failed attempt and never exceeds `max`:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::BackoffConfig;
+//! use std::time::Duration;
+//! let backoff = BackoffConfig{ jitter: false, ..Default::default() };
+//! assert_eq!(backoff.delay(0), Duration::from_millis(100));
+//! assert_eq!(backoff.delay(1), Duration::from_millis(200));
+//! assert_eq!(backoff.delay(20), Duration::from_secs(30));
+//! ```
+
+use std::time::Duration;
+use error;
+use properties;
+use GenResult;
+
+
+/// Backoff policy for reconnecting to the broker
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackoffConfig{
+    /// Delay before the first retry
+    pub initial: Duration,
+    /// Upper bound for the delay, no matter how many attempts failed
+    pub max: Duration,
+    /// Factor the delay grows by after each failed attempt
+    pub multiplier: f64,
+    /// Randomize each delay between half and the full value, so a fleet of \
+    /// workers doesn't reconnect in lockstep after an outage
+    pub jitter: bool
+}
+
+
+impl Default for BackoffConfig{
+    fn default() -> Self{
+        BackoffConfig{
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true
+        }
+    }
+}
+
+
+impl BackoffConfig{
+    /// Returns how long to wait before the given retry (starting at 0)
+    pub fn delay(&self, attempt: u32) -> Duration{
+        let initial = duration_as_secs(self.initial);
+        let max = duration_as_secs(self.max);
+        let mut delay = initial * self.multiplier.powi(attempt as i32);
+        if !delay.is_finite() || delay > max { delay = max; }
+        if self.jitter {
+            delay = delay / 2.0 + delay / 2.0 * random_fraction();
+        }
+        Duration::new(delay.trunc() as u64, (delay.fract() * 1e9) as u32)
+    }
+}
+
+
+/// Run an operation (e.g. a post) until it succeeds, at most `attempts` \
+/// times, waiting with the backoff between the attempts. Only errors that \
+/// may go away are retried: a lost connection, a timeout, flow control or \
+/// a full queue (see `BenderMqError::is_retryable()`). Every other error, \
+/// like a job that doesn't serialize or missing permissions, is returned \
+/// right away, as it would fail the same way again:
+/// ```
+/// # extern crate bender_mq;
+/// # extern crate serde_json;
+/// # use bender_mq::{BackoffConfig, BenderMqError, retry};
+/// use std::time::Duration;
+/// # fn main(){
+/// let backoff = BackoffConfig{ initial: Duration::from_millis(1), jitter: false, ..Default::default() };
+///
+/// let mut calls = 0;
+/// let result: Result<(), _> = retry(&backoff, 5, ||{
+///     calls += 1;
+///     Err(From::from(serde_json::from_str::<u32>("x").unwrap_err()))
+/// });
+/// assert!(result.is_err());
+/// assert_eq!(calls, 1);
+///
+/// let mut calls = 0;
+/// let result = retry(&backoff, 5, ||{
+///     calls += 1;
+///     if calls < 3 { Err(Box::new(BenderMqError::Timeout)) } else { Ok(calls) }
+/// });
+/// assert_eq!(result.unwrap(), 3);
+/// # }
+/// ```
+pub fn retry<T, F>(backoff: &BackoffConfig, attempts: u32, mut operation: F) -> GenResult<T> where F: FnMut() -> GenResult<T>{
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                attempt += 1;
+                if attempt >= attempts || !error::is_retryable(&err) { return Err(err); }
+                let delay = backoff.delay(attempt - 1);
+                println!("Warning: {}, retrying in {:?}", err, delay);
+                std::thread::sleep(delay);
+            }
+        }
+    }
+}
+
+
+fn duration_as_secs(duration: Duration) -> f64{
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+
+/// A random number between 0.0 and 1.0 (see `properties::random_u64()`)
+fn random_fraction() -> f64{
+    properties::random_u64() as f64 / u64::MAX as f64
+}
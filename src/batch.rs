@@ -0,0 +1,163 @@
+//! Helpers to publish several json values as one message. A batch is \
+//! framed as a plain json array, so any json parser can read it.
+
+use std::time::{Duration, Instant};
+use amqp::protocol::basic::BasicProperties;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use {BenderMQ, GenResult};
+
+
+/// RabbitMQ refuses messages larger than this by default (`max_message_size`)
+pub const MAX_MESSAGE_SIZE: usize = 128 * 1024 * 1024;
+
+/// How many bytes of json a `BatchedPublisher` collects by default
+const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+/// How long a `BatchedPublisher` holds back messages by default
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+
+/// Collects small messages and publishes them as one json array, either once \
+/// they add up to `max_bytes()` or when the oldest one waited for \
+/// `interval()`. The interval is checked whenever a message is pushed or \
+/// `flush_if_due()` is called, there is no timer thread. Whatever is left is \
+/// flushed when the publisher is dropped. Consumers split a batch with \
+/// `explode_batch()`:
+/// ```no_run
+/// # extern crate bender_mq;
+/// # use bender_mq::{BatchedPublisher, Channel, BenderMQ};
+/// # use std::time::Duration;
+/// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+/// let mut publisher = BatchedPublisher::new(&mut channel, "info-topic", "telemetry.batch")
+///     .interval(Duration::from_millis(200));
+/// for frame in 0..10_000 {
+///     publisher.push(&frame).expect("Couldn't publish batch");
+/// }
+/// publisher.flush().expect("Couldn't publish batch");
+/// ```
+pub struct BatchedPublisher<'a, B: 'a + BenderMQ>{
+    mq: &'a mut B,
+    exchange: String,
+    routing_key: String,
+    max_bytes: usize,
+    interval: Duration,
+    pending: Vec<String>,
+    pending_bytes: usize,
+    oldest: Option<Instant>
+}
+
+
+impl<'a, B: BenderMQ> BatchedPublisher<'a, B>{
+    /// Collect messages for the given exchange and routing key
+    pub fn new<S>(mq: &'a mut B, exchange: S, routing_key: S) -> Self where S: Into<String>{
+        BatchedPublisher{
+            mq,
+            exchange: exchange.into(),
+            routing_key: routing_key.into(),
+            max_bytes: DEFAULT_MAX_BYTES,
+            interval: DEFAULT_INTERVAL,
+            pending: Vec::new(),
+            pending_bytes: 0,
+            oldest: None
+        }
+    }
+
+    /// Publish once the collected json adds up to this many bytes, 64 KiB \
+    /// by default
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self{
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Publish once the oldest collected message waited this long, one \
+    /// second by default
+    pub fn interval(mut self, interval: Duration) -> Self{
+        self.interval = interval;
+        self
+    }
+
+    /// The number of messages waiting to be published
+    pub fn pending(&self) -> usize{
+        self.pending.len()
+    }
+
+    /// Serialize a message and add it to the batch, publishing the batch if \
+    /// it is full or due
+    pub fn push<T>(&mut self, message: &T) -> GenResult<()> where T: Serialize{
+        let json = serde_json::to_string(message)?;
+        self.pending_bytes += json.len() + 1;
+        self.pending.push(json);
+        if self.oldest.is_none() { self.oldest = Some(Instant::now()); }
+        if self.pending_bytes >= self.max_bytes {
+            self.flush()
+        } else {
+            self.flush_if_due()
+        }
+    }
+
+    /// Publish the batch if the oldest message waited for the interval
+    pub fn flush_if_due(&mut self) -> GenResult<()>{
+        match self.oldest {
+            Some(oldest) if oldest.elapsed() >= self.interval => self.flush(),
+            _ => Ok(())
+        }
+    }
+
+    /// Publish the collected messages right away
+    pub fn flush(&mut self) -> GenResult<()>{
+        if self.pending.is_empty() { return Ok(()); }
+        for chunk in json_array_chunks(&self.pending, MAX_MESSAGE_SIZE) {
+            let properties = BasicProperties{ content_type: Some("application/json".to_string()), ..Default::default()};
+            self.mq.publish(self.exchange.as_str(), self.routing_key.as_str(), properties, chunk.into_bytes())?;
+        }
+        self.pending.clear();
+        self.pending_bytes = 0;
+        self.oldest = None;
+        Ok(())
+    }
+}
+
+
+impl<'a, B: BenderMQ> Drop for BatchedPublisher<'a, B>{
+    fn drop(&mut self){
+        if let Err(err) = self.flush() {
+            println!("Error: Dropping {} batched messages for {}: {}", self.pending.len(), self.routing_key, err);
+        }
+    }
+}
+
+
+/// Split a batch published by `BatchedPublisher` (or `post_tasks_info()`) \
+/// back into its messages:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::explode_batch;
+/// let frames: Vec<u32> = explode_batch(b"[1,2,3]").expect("Not a batch");
+/// assert_eq!(frames, vec![1, 2, 3]);
+/// ```
+pub fn explode_batch<T>(body: &[u8]) -> GenResult<Vec<T>> where T: DeserializeOwned{
+    Ok(serde_json::from_slice(body)?)
+}
+
+
+/// Join serialized json values into json arrays that are at most `max_size` \
+/// bytes long each. A single value that is larger than `max_size` ends up in \
+/// an array of its own.
+pub fn json_array_chunks(values: &[String], max_size: usize) -> Vec<String>{
+    let mut chunks = Vec::new();
+    let mut chunk = String::from("[");
+    for value in values {
+        // The comma and the closing bracket need to fit as well
+        if chunk.len() > 1 && chunk.len() + value.len() + 2 > max_size {
+            chunk.push(']');
+            chunks.push(chunk);
+            chunk = String::from("[");
+        }
+        if chunk.len() > 1 { chunk.push(','); }
+        chunk.push_str(value);
+    }
+    chunk.push(']');
+    chunks.push(chunk);
+    chunks
+}
@@ -0,0 +1,120 @@
+//! The server properties a broker announces in `connection.start` (product, \
+//! version, capabilities), so features that need a recent broker can fail \
+//! with a legible error instead of the one the broker returns on \
+//! declaration. The amqp crate reads these properties while opening a \
+//! `Session` and drops them, so they are read on a short connection of \
+//! their own: the broker sends `connection.start` right after the protocol \
+//! header, before any login, and the socket is closed after that frame.
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::Capabilities;
+//! # fn main(){
+//! let broker = Capabilities{ version: Some("3.7.28".to_string()), ..Default::default() };
+//! assert!(broker.version_at_least(3, 7));
+//! assert!(!broker.version_at_least(3, 8));
+//! let err = broker.require_version(3, 8, "quorum queues").unwrap_err();
+//! assert_eq!(err.to_string(), "quorum queues require RabbitMQ 3.8+ (the broker is 3.7.28)");
+//! # }
+//! ```
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::Duration;
+use amq_proto::Frame;
+use amqp::{Table, TableEntry, protocol};
+use error::BenderMqError;
+use frames;
+use GenResult;
+
+
+/// How long to wait for the broker to send `connection.start`
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// What the broker told about itself in `connection.start`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities{
+    /// The broker product, e.g. `RabbitMQ`
+    pub product: Option<String>,
+    /// The broker version, e.g. `3.8.9`
+    pub version: Option<String>,
+    /// The platform the broker runs on, e.g. `Erlang/OTP 23.1`
+    pub platform: Option<String>,
+    /// The entries of the `capabilities` table the broker set, e.g. \
+    /// `publisher_confirms` or `consumer_priorities`, sorted
+    pub capabilities: Vec<String>,
+    /// The SASL mechanisms the broker accepts, e.g. `PLAIN`
+    pub mechanisms: Vec<String>
+}
+
+
+impl Capabilities{
+    /// Read the capabilities from the `connection.start` of a broker
+    pub fn from_start(start: &protocol::connection::Start) -> Self{
+        let properties = &start.server_properties;
+        let mut capabilities: Vec<String> = match properties.get("capabilities") {
+            Some(&TableEntry::FieldTable(ref table)) => table.iter()
+                .filter(|&(_, value)| *value == TableEntry::Bool(true))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            _ => Vec::new()
+        };
+        capabilities.sort();
+        Capabilities{
+            product: string(properties, "product"),
+            version: string(properties, "version"),
+            platform: string(properties, "platform"),
+            capabilities,
+            mechanisms: start.mechanisms.split_whitespace().map(|mechanism| mechanism.to_string()).collect()
+        }
+    }
+
+    /// Returns true if the broker announced the capability
+    pub fn supports(&self, capability: &str) -> bool{
+        self.capabilities.iter().any(|name| name == capability)
+    }
+
+    /// Returns true if the broker version is at least `major.minor`. A \
+    /// broker that didn't tell its version is assumed to be older
+    pub fn version_at_least(&self, major: u32, minor: u32) -> bool{
+        let version = match self.version {
+            Some(ref version) => version,
+            None => return false
+        };
+        // Pre-releases like 3.8.0-beta.4 count as their release
+        let mut parts = version.split(|c: char| !c.is_ascii_digit()).map(|part| part.parse::<u32>().unwrap_or(0));
+        let found = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+        found >= (major, minor)
+    }
+
+    /// Fail with a `BenderMqError::UnsupportedBroker` error naming the \
+    /// feature if the broker is older than `major.minor`
+    pub fn require_version(&self, major: u32, minor: u32, feature: &str) -> GenResult<()>{
+        if self.version_at_least(major, minor) { return Ok(()); }
+        let found = self.version.clone().unwrap_or_else(|| "of an unknown version".to_string());
+        Err(Box::new(BenderMqError::UnsupportedBroker(feature.to_string(), format!("RabbitMQ {}.{}+", major, minor), found)))
+    }
+}
+
+
+/// Connect to the broker, read its `connection.start` and hang up again
+pub fn read(host: &str, port: u16) -> GenResult<Capabilities>{
+    let mut stream = TcpStream::connect((host, port))?;
+    stream.set_read_timeout(Some(START_TIMEOUT))?;
+    stream.write_all(b"AMQP\x00\x00\x09\x01")?;
+    let frame = Frame::decode(&mut stream)?;
+    if frames::method_name(&frame) != Some("connection.start") {
+        return Err(From::from(format!("The broker at {}:{} didn't start with connection.start", host, port)));
+    }
+    let start: protocol::connection::Start = frames::decode(&frame)?;
+    Ok(Capabilities::from_start(&start))
+}
+
+
+/// The value of a string property
+fn string(properties: &Table, name: &str) -> Option<String>{
+    match properties.get(name) {
+        Some(&TableEntry::LongString(ref value)) => Some(value.clone()),
+        _ => None
+    }
+}
@@ -0,0 +1,99 @@
+//! Bookkeeping for publisher confirms. Once a channel is in confirm mode the \
+//! broker numbers every published message, starting at 1, and eventually \
+//! answers with a `basic.ack` (or `basic.nack`) for that delivery tag. With \
+//! `multiple` set, one answer settles every outstanding tag up to it.
+//!
+//! Only the nacks are stored. A tag that was published and is neither \
+//! outstanding nor nacked has been acked, so a long running producer \
+//! doesn't pile up an entry for every message it sent.
+
+use std::collections::BTreeSet;
+use amq_proto::Frame;
+use amqp::protocol::basic::{Ack, Nack};
+use frames;
+use GenResult;
+
+
+/// Tracks the delivery tags published in confirm mode and their outcome
+#[derive(Debug, Default)]
+pub struct Confirms{
+    next_tag: u64,
+    outstanding: BTreeSet<u64>,
+    nacked: BTreeSet<u64>
+}
+
+
+impl Confirms{
+    pub fn new() -> Self{
+        Confirms{ next_tag: 1, ..Default::default() }
+    }
+
+    /// The delivery tag the next published message will get
+    pub fn next_tag(&self) -> u64{
+        self.next_tag
+    }
+
+    /// Register a message that has just been published
+    pub fn published(&mut self) -> u64{
+        let tag = self.next_tag;
+        self.outstanding.insert(tag);
+        self.next_tag += 1;
+        tag
+    }
+
+    /// Record the outcome if the frame is a `basic.ack` or `basic.nack`. \
+    /// Returns whether the frame was a confirm
+    pub fn record(&mut self, frame: &Frame) -> GenResult<bool>{
+        let (tag, multiple, acked) = match frames::method_name(frame){
+            Some("basic.ack") => {
+                let ack: Ack = frames::decode(frame)?;
+                (ack.delivery_tag, ack.multiple, true)
+            },
+            Some("basic.nack") => {
+                let nack: Nack = frames::decode(frame)?;
+                (nack.delivery_tag, nack.multiple, false)
+            },
+            _ => return Ok(false)
+        };
+        let tags: Vec<u64> = if multiple {
+            self.outstanding.range(..=tag).cloned().collect()
+        } else {
+            vec![tag]
+        };
+        for tag in tags {
+            if self.outstanding.remove(&tag) && !acked {
+                self.nacked.insert(tag);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The number of published messages the broker hasn't answered yet
+    pub fn pending(&self) -> usize{
+        self.outstanding.len()
+    }
+
+    /// The delivery tags the broker hasn't answered yet, oldest first
+    pub fn outstanding(&self) -> Vec<u64>{
+        self.outstanding.iter().cloned().collect()
+    }
+
+    /// Returns (and forgets) the tags the broker nacked that haven't been \
+    /// taken with `take()`, oldest first
+    pub fn take_nacked(&mut self) -> Vec<u64>{
+        let nacked = self.nacked.iter().cloned().collect();
+        self.nacked.clear();
+        nacked
+    }
+
+    /// Returns the outcome for a tag: `Some(true)` if the broker acked it, \
+    /// `Some(false)` if it nacked it and None if there is no answer yet or \
+    /// the tag was never published. A nack is forgotten once it was taken
+    pub fn take(&mut self, tag: u64) -> Option<bool>{
+        if tag == 0 || tag >= self.next_tag || self.outstanding.contains(&tag) {
+            None
+        } else {
+            Some(!self.nacked.remove(&tag))
+        }
+    }
+}
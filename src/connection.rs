@@ -0,0 +1,1217 @@
+//! A Connection owns the amqp `Session` together with the `Channel` that was \
+//! opened on it. `Channel::open_channel()` drops the session right after the \
+//! channel has been opened, a Connection keeps it alive for as long as the \
+//! channel is used and closes both in order when you are done:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::{Connection, BenderMQ};
+//! let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+//! connection.declare_topic_exchange().expect("Declaration of topic exchange failed");
+//! connection.close().expect("Couldn't close the connection");
+//! ```
+//!
+//! Note that `connection.blocked` and `connection.unblocked` notifications \
+//! (sent when the broker runs low on memory or disk) can't be surfaced yet: \
+//! the amqp crate accepts them on channel 0, which stays private to its \
+//! `Session` and is never read after the handshake. A producer stalls on \
+//! publish while the connection is blocked. Publishing paused via \
+//! `channel.flow` is reported by `is_flow_blocked()`.
+//!
+//! The server properties of the handshake (broker version, capabilities and \
+//! so on) are dropped by the amqp crate after opening the `Session`. \
+//! `server_capabilities()` asks the broker for them again on a short \
+//! connection of its own.
+//!
+//! A Connection can't be opened over a transport the caller already holds \
+//! (e.g. a pre-authenticated unix socket of a sidecar): the amqp crate always \
+//! connects with `TcpStream::connect()` to the host and port of the url and \
+//! keeps the socket private. Where the broker is only reachable through such \
+//! a transport, expose it as a TCP port on localhost (e.g. with \
+//! `socat TCP-LISTEN:5672,bind=127.0.0.1,fork UNIX-CONNECT:/run/amqp.sock`) \
+//! and open the Connection to `amqp://127.0.0.1:5672`.
+//!
+//! For the same reason socket options like TCP keepalive can't be set. Linux \
+//! only sends keepalive probes on sockets that opted in, so the sysctls \
+//! alone don't help. Where a NAT or firewall drops idle connections, keep \
+//! the connection busy instead, e.g. by posting a small status message to \
+//! `info-topic` more often than the middlebox timeout.
+//!
+//! The SASL mechanism can't be chosen either: the amqp crate always answers \
+//! `connection.start` with `PLAIN` and the login and password of the url. \
+//! `AMQPLAIN` or `EXTERNAL` (authenticating with a TLS client certificate) \
+//! would need a client that negotiates them, and the certificate would \
+//! need TLS, which is off in the amqp crate as used here. For a broker that \
+//! only accepts `EXTERNAL`, terminate TLS with the client certificate in a \
+//! local proxy (e.g. `stunnel` or `socat` with `OPENSSL:`) and enable \
+//! `PLAIN` for connections from that proxy only.
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use amqp::{AMQPError, Basic, Channel, Options, Session, Table, protocol};
+use amqp::protocol::basic::BasicProperties;
+use amq_proto::Frame;
+use bender_job::task::Task;
+use bender_job::Job;
+use backoff::BackoffConfig;
+use capabilities::{self, Capabilities};
+use confirms::Confirms;
+use declared::Declared;
+use profile::SerializationProfile;
+use properties;
+use queue::{self, Overflow};
+use session;
+use options::PublishOptions;
+use routing::{DefaultRoutingStrategy, RoutingStrategy};
+use error::BenderMqError;
+use flow::{self, Intercepted};
+use hooks::Hooks;
+#[cfg(feature = "metrics")]
+use metrics::Histogram;
+use {BenderMQ, GenResult};
+
+
+/// The amqp reply code for a regular shutdown
+const REPLY_SUCCESS: u16 = 200;
+
+/// The first channel `all_queue_depths()` counts on, so a missing queue \
+/// doesn't close the main one. Each probe channel the broker closes is \
+/// replaced by one with the next id
+const PROBE_CHANNEL: u16 = 2;
+
+/// The queues `all_queue_depths()` reports
+const KNOWN_QUEUES: [&str; 4] = ["info", "job", "work", "worker"];
+
+/// How long `timed_post_job()` waits for the broker to confirm
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+
+/// What a Connection does when a message is published to an exchange that \
+/// hasn't been declared on it. The broker closes the channel when the \
+/// exchange doesn't exist, which kills it for all following calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndeclaredExchange{
+    /// Publish anyways, the exchange may have been declared by someone else
+    Publish,
+    /// Return a `BenderMqError::ExchangeNotDeclared` error without publishing
+    Fail,
+    /// Declare the exchange with `declare_exchange()` first
+    Declare
+}
+
+
+/// How a Connection was shut down by `close_gracefully()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseOutcome{
+    /// The broker answered every published message before closing
+    Clean,
+    /// The timeout elapsed while the broker still owed this many confirms. \
+    /// These messages may or may not have arrived
+    PendingAcks(usize)
+}
+
+
+/// The confirms `drain_confirms()` found unsettled or refused
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfirmReport{
+    /// Delivery tags the broker nacked that weren't awaited with \
+    /// `wait_for_confirm()`. These messages didn't arrive
+    pub nacked: Vec<u64>,
+    /// Delivery tags the broker still owed a confirm for when the timeout \
+    /// elapsed. These messages may or may not have arrived
+    pub unconfirmed: Vec<u64>
+}
+
+
+impl ConfirmReport{
+    /// True if every message was acked
+    pub fn is_clean(&self) -> bool{
+        self.nacked.is_empty() && self.unconfirmed.is_empty()
+    }
+}
+
+
+/// An amqp Session together with the Channel opened on it
+pub struct Connection{
+    session: Session,
+    /// Empty while a read that outlived its deadline still holds the channel
+    channel: Option<Channel>,
+    /// Hands the channel back together with the frame once that read is done
+    pending_read: Option<Receiver<(Channel, Result<Frame, AMQPError>)>>,
+    /// The options the session was opened with, used by `reconnect()`
+    options: Options,
+    /// Prefetch count and size set with `set_prefetch()`
+    prefetch: Option<(u16, Option<u32>)>,
+    /// Prefetch count set with `set_prefetch_global()`
+    prefetch_global: Option<u16>,
+    /// The last json posted by `post_job_debounced()` for each job id
+    last_posted: HashMap<String, String>,
+    /// When `post_job_idempotent()` published each job hash
+    published_hashes: HashMap<u64, Instant>,
+    /// Set once the channel has been put into confirm mode
+    confirms: Option<Confirms>,
+    /// Template merged into the properties of every published message
+    default_properties: Option<BasicProperties>,
+    /// Exchanges and queues that have been declared on this connection
+    declared: Declared,
+    /// Queues declared with `Overflow::RejectPublish` on this connection
+    reject_publish: HashSet<String>,
+    /// The exchange tasks are posted to
+    work_exchange: String,
+    /// Whether the `work` queue is declared lazy
+    lazy_work_queue: bool,
+    /// Decides the routing keys of job updates and tasks
+    routing_strategy: Box<RoutingStrategy>,
+    /// Applied to serialized jobs and tasks before they are posted
+    serialization_profile: SerializationProfile,
+    /// How to handle publishes to exchanges that haven't been declared
+    undeclared_exchange: UndeclaredExchange,
+    /// The flags the post methods publish with
+    publish_options: PublishOptions,
+    /// Queue, arguments, no_ack and exclusive flag of every consumer started \
+    /// on this connection by tag
+    consumers: HashMap<String, (String, Table, bool, bool)>,
+    /// Set while the consumers are cancelled by `pause()`
+    paused: bool,
+    /// Set while the broker asks us to stop publishing via `channel.flow`
+    flow_blocked: bool,
+    /// The publish latencies measured by `timed_post_job()`
+    #[cfg(feature = "metrics")]
+    publish_latency: Histogram
+}
+
+
+/// Opens a Connection with tuned session options. Unless an url is given \
+/// the one from the config is used:
+/// ```no_run
+/// # extern crate bender_mq;
+/// # use bender_mq::Connection;
+/// let connection = Connection::builder()
+///     .url("amqp://localhost//")
+///     .frame_max(1024 * 1024)
+///     .open()
+///     .expect("Couldn't aquire connection.");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionBuilder{
+    url: Option<String>,
+    frame_max: Option<u32>,
+    channel_max: Option<u16>
+}
+
+
+impl ConnectionBuilder{
+    /// Connect to this url instead of the one from the config
+    pub fn url<S>(mut self, url: S) -> Self where S: Into<String>{
+        self.url = Some(url.into());
+        self
+    }
+
+    /// The largest frame (in bytes) to propose to the broker, 128 KiB by \
+    /// default. Messages bigger than a frame are split into several ones, so \
+    /// large payloads travel more efficiently with bigger frames. The broker \
+    /// has a limit of its own (`frame_max` in rabbitmq.conf) and the smaller \
+    /// of both is used
+    pub fn frame_max(mut self, frame_max: u32) -> Self{
+        self.frame_max = Some(frame_max);
+        self
+    }
+
+    /// The highest number of channels to propose to the broker. As with \
+    /// `frame_max()` the smaller of this and the broker's limit wins. Note \
+    /// that amqp 0.1.3 doesn't pass this on yet and always proposes 65535
+    pub fn channel_max(mut self, channel_max: u16) -> Self{
+        self.channel_max = Some(channel_max);
+        self
+    }
+
+    /// Open the session with these options and a channel on it
+    pub fn open(self) -> GenResult<Connection>{
+        let url = match self.url {
+            Some(url) => url,
+            None => session::url_from_config()?
+        };
+        let mut options = session::options_from_url(url.as_str())?;
+        if let Some(frame_max) = self.frame_max { options.frame_max_limit = frame_max; }
+        if let Some(channel_max) = self.channel_max { options.channel_max_limit = channel_max; }
+        Connection::open_options(options)
+    }
+}
+
+
+impl Connection{
+    /// Start building a Connection with tuned session options
+    pub fn builder() -> ConnectionBuilder{
+        ConnectionBuilder::default()
+    }
+
+    /// Open a session to the given url and a channel on it
+    fn open_url(url: &str) -> GenResult<Self>{
+        Connection::open_options(session::options_from_url(url)?)
+    }
+
+    /// Open a session with the given options and a channel on it
+    fn open_options(options: Options) -> GenResult<Self>{
+        let (session, channel) = Connection::open_session(&options)?;
+        Ok(Connection{
+            session,
+            channel: Some(channel),
+            pending_read: None,
+            options,
+            prefetch: None,
+            prefetch_global: None,
+            last_posted: HashMap::new(),
+            published_hashes: HashMap::new(),
+            confirms: None,
+            default_properties: None,
+            declared: Declared::default(),
+            reject_publish: HashSet::new(),
+            work_exchange: "work".to_string(),
+            lazy_work_queue: false,
+            routing_strategy: Box::new(DefaultRoutingStrategy),
+            serialization_profile: SerializationProfile::AsIs,
+            undeclared_exchange: UndeclaredExchange::Publish,
+            publish_options: PublishOptions::default(),
+            consumers: HashMap::new(),
+            paused: false,
+            flow_blocked: false,
+            #[cfg(feature = "metrics")]
+            publish_latency: Histogram::default()
+        })
+    }
+
+    /// Open a session with the given options and a channel on it
+    fn open_session(options: &Options) -> GenResult<(Session, Channel)>{
+        let mut session = Session::new(session::copy_options(options)).map_err(BenderMqError::from)?;
+        let channel = session.open_channel(1).map_err(BenderMqError::from)?;
+        Ok((session, channel))
+    }
+
+    /// Replace the session and channel with new ones, e.g. after the broker \
+    /// went away, retrying with backoff like `open_channel_with_backoff()`. \
+    /// Everything that was set up on the old channel is applied again: \
+    /// confirm mode (if enabled) and the prefetch of `set_prefetch()` and \
+    /// `set_prefetch_global()`. The settings that only live on this side \
+    /// (default properties, publish options, the work exchange, ...) stay \
+    /// as they are. Confirms the old channel still owed are lost, the \
+    /// declare cache is cleared and consumers have to be started again, as \
+    /// their callbacks lived on the old channel:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, BackoffConfig, RoutingKey};
+    /// # fn example(connection: &mut Connection, status: RoutingKey){
+    /// if connection.post_to_info(status, "alive").is_err() {
+    ///     connection.reconnect(&BackoffConfig::default(), 10).expect("Couldn't reconnect");
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn reconnect(&mut self, backoff: &BackoffConfig, attempts: u32) -> GenResult<()>{
+        let mut attempt = 0;
+        let (session, channel) = loop {
+            match Connection::open_session(&self.options) {
+                Ok(opened) => break opened,
+                Err(err) => {
+                    attempt += 1;
+                    if attempt >= attempts { return Err(err); }
+                    let delay = backoff.delay(attempt - 1);
+                    println!("Warning: Couldn't reconnect to {} ({}), retrying in {:?}", self.options.host, err, delay);
+                    std::thread::sleep(delay);
+                }
+            }
+        };
+        // The old session is most likely dead, so it is dropped without closing
+        self.session = session;
+        self.channel = Some(channel);
+        self.pending_read = None;
+        if let Some(confirms) = self.confirms.take() {
+            if confirms.pending() > 0 {
+                println!("Warning: Lost {} pending confirms while reconnecting", confirms.pending());
+            }
+            self.enable_confirms()?;
+        }
+        if let Some((count, size)) = self.prefetch {
+            self.channel().set_prefetch(count, size)?;
+        }
+        if let Some(count) = self.prefetch_global {
+            self.channel().set_prefetch_global(count)?;
+        }
+        self.declared.clear();
+        self.consumers.clear();
+        self.paused = false;
+        self.flow_blocked = false;
+        Ok(())
+    }
+
+    /// Serialize a job and post it to the `job` exchange like `post_job()`, \
+    /// unless it serializes to exactly the same json that was posted for this \
+    /// job id before. Returns whether the job has been posted:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # fn example(job: &bender_job::Job){
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// assert!(connection.post_job_debounced(job).expect("Couldn't post job"));
+    /// assert!(!connection.post_job_debounced(job).expect("Couldn't post job"));
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn post_job_debounced(&mut self, job: &Job) -> GenResult<bool>{
+        let json = self.serialization_profile.apply(job.serialize()?)?;
+        let id = job.id();
+        if self.last_posted.get(&id) == Some(&json) { return Ok(false); }
+        self.post_to_job(json.as_str())?;
+        self.last_posted.insert(id, json);
+        Ok(true)
+    }
+
+    /// Serialize a job and post it to the `job` exchange like `post_job()`, \
+    /// unless a job with exactly the same json has been posted by this \
+    /// method within the given time window. Unlike `post_job_debounced()` \
+    /// this compares the whole content instead of the last state per id, so \
+    /// a client retrying the same job doesn't enqueue it twice. Returns \
+    /// whether the job has been posted:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # use std::time::Duration;
+    /// # fn example(job: &bender_job::Job){
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// let window = Duration::from_secs(60);
+    /// assert!(connection.post_job_idempotent(job, window).expect("Couldn't post job"));
+    /// assert!(!connection.post_job_idempotent(job, window).expect("Couldn't post job"));
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn post_job_idempotent(&mut self, job: &Job, window: Duration) -> GenResult<bool>{
+        let json = self.serialization_profile.apply(job.serialize()?)?;
+        let mut hasher = DefaultHasher::new();
+        json.hash(&mut hasher);
+        let hash = hasher.finish();
+        // Forget everything that left the window, so the set stays small
+        self.published_hashes.retain(|_, published| published.elapsed() < window);
+        if self.published_hashes.contains_key(&hash) { return Ok(false); }
+        self.post_to_job(json.as_str())?;
+        self.published_hashes.insert(hash, Instant::now());
+        Ok(true)
+    }
+
+    /// Set properties that every message published from now on carries, \
+    /// unless the post method sets a value of its own. To make all messages \
+    /// persistent for example:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # use amqp::protocol::basic::BasicProperties;
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_default_properties(BasicProperties{
+    ///     app_id: Some("bender-flaskbender".to_string()),
+    ///     delivery_mode: Some(2),
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn set_default_properties(&mut self, properties: BasicProperties){
+        self.default_properties = Some(properties);
+    }
+
+    /// Stamp every message published from now on with the `cluster_id` \
+    /// property, naming the bender cluster that produced it. Once messages \
+    /// are federated or shoveled to another broker, a consumer there can \
+    /// still tell where they came from. The other default properties stay \
+    /// as they are. The config has no field for it (yet), so pass the name \
+    /// from wherever your deployment keeps it:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// let cluster_id = std::env::var("BENDER_CLUSTER_ID").unwrap_or_else(|_| "bender-eu".to_string());
+    /// connection.set_cluster_id(cluster_id);
+    /// ```
+    pub fn set_cluster_id<S>(&mut self, cluster_id: S) where S: Into<String>{
+        let mut properties = self.default_properties.take().unwrap_or_default();
+        properties.cluster_id = Some(cluster_id.into());
+        self.default_properties = Some(properties);
+    }
+
+    /// A Connection remembers which exchanges and queues it declared, so \
+    /// calling the `declare_*` and `create_*` methods again is a no-op that \
+    /// doesn't go to the broker. A new connection (e.g. after a reconnect) \
+    /// starts with nothing declared. Turn this off to always declare again:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_declare_caching(false);
+    /// connection.declare_topic_exchange().expect("Declaration of topic exchange failed");
+    /// connection.declare_topic_exchange().expect("Declaration of topic exchange failed");
+    /// ```
+    pub fn set_declare_caching(&mut self, enabled: bool){
+        self.declared.set_enabled(enabled);
+    }
+
+    /// Forget which exchanges and queues have been declared, e.g. after they \
+    /// have been deleted on the broker
+    pub fn clear_declare_cache(&mut self){
+        self.declared.clear();
+    }
+
+    /// Set the flags all post methods publish with from now on. To stop \
+    /// messages that can't be routed from being returned:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, PublishOptions};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_publish_options(PublishOptions{ mandatory: false, ..Default::default() });
+    /// ```
+    pub fn set_publish_options(&mut self, options: PublishOptions){
+        self.publish_options = options;
+    }
+
+    /// Post tasks to another exchange than `work` from now on, e.g. to cut \
+    /// all producers over to a new exchange during a migration without \
+    /// redeploying them:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_work_exchange("work-v2");
+    /// assert_eq!(connection.work_exchange(), "work-v2");
+    /// ```
+    pub fn set_work_exchange<S>(&mut self, name: S) where S: Into<String>{
+        self.work_exchange = name.into();
+    }
+
+    /// Route job updates and tasks with another `RoutingStrategy` from now \
+    /// on. Only `post_job_info()` and `post_task()` (and the methods built on \
+    /// them) consult it, methods that take a routing key or post to a fixed \
+    /// one stay as they are
+    pub fn set_routing_strategy<R>(&mut self, strategy: R) where R: RoutingStrategy + 'static{
+        self.routing_strategy = Box::new(strategy);
+    }
+
+    /// Declare the `work` queue lazy from now on (see `lazy_work_queue()`), \
+    /// e.g. from a flag in your config for the machines that submit large \
+    /// batches:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_lazy_work_queue(true);
+    /// connection.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// connection.create_work_queue().expect("Declaration of work queue failed");
+    /// ```
+    pub fn set_lazy_work_queue(&mut self, lazy: bool){
+        self.lazy_work_queue = lazy;
+    }
+
+    /// Post jobs and tasks with another `SerializationProfile` from now on \
+    /// (see `serialization_profile()`), e.g. when everything this connection \
+    /// posts is read by a consumer that expects camelCase fields:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Connection, BenderMQ, SerializationProfile};
+    /// # fn example(job: &bender_job::Job){
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_serialization_profile(SerializationProfile::CamelCase);
+    /// connection.post_job_info(job).expect("Couldn't post job");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn set_serialization_profile(&mut self, profile: SerializationProfile){
+        self.serialization_profile = profile;
+    }
+
+    /// Choose what happens when a message is published to an exchange that \
+    /// hasn't been declared on this connection. By default it is published \
+    /// anyways. To get an error instead of a dead channel when e.g. \
+    /// `post_to_work()` is called before `declare_work_exchange()`:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, RoutingKey, UndeclaredExchange};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_undeclared_exchange(UndeclaredExchange::Fail);
+    /// let routing_key = RoutingKey::new("1.cycles").expect("Invalid routing key");
+    /// let err = connection.post_to_work_routed(routing_key, "{}").unwrap_err();
+    /// assert_eq!(err.to_string(), "exchange 'work' not declared");
+    /// ```
+    pub fn set_undeclared_exchange(&mut self, undeclared_exchange: UndeclaredExchange){
+        self.undeclared_exchange = undeclared_exchange;
+    }
+
+    /// Check the exchange against the declared ones before publishing to it
+    fn check_exchange(&mut self, exchange: &str) -> GenResult<()>{
+        // The default exchange always exists
+        if exchange.is_empty() || self.declared.recorded(format!("exchange {}", exchange).as_str()) {
+            return Ok(());
+        }
+        match self.undeclared_exchange {
+            UndeclaredExchange::Publish => Ok(()),
+            UndeclaredExchange::Fail => Err(Box::new(BenderMqError::ExchangeNotDeclared(exchange.to_string()))),
+            UndeclaredExchange::Declare => self.declare_exchange(exchange)
+        }
+    }
+
+    /// Cancel every consumer started on this connection, e.g. before \
+    /// subscribing to other queues while reconfiguring. Unlike `pause()` \
+    /// they are forgotten afterwards, so `resume()` won't subscribe them \
+    /// again. Messages that were already sent before the cancel are still \
+    /// handed to the callbacks while the channel is read:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # use amqp::Table;
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.start_consumer("work.cycles", Table::new(), |_|{}).expect("Consuming failed");
+    /// connection.cancel_all_consumers().expect("Couldn't cancel consumers");
+    /// connection.start_consumer("work.eevee", Table::new(), |_|{}).expect("Consuming failed");
+    /// ```
+    pub fn cancel_all_consumers(&mut self) -> GenResult<()>{
+        let tags: Vec<String> = self.consumers.keys().cloned().collect();
+        for tag in tags {
+            // Paused consumers have already been cancelled on the broker
+            if !self.paused {
+                self.channel().basic_cancel(tag.clone(), false).map_err(BenderMqError::from)?;
+            }
+            self.consumers.remove(&tag);
+        }
+        self.paused = false;
+        Ok(())
+    }
+
+    /// Stop the broker from delivering new messages to the consumers started \
+    /// on this connection, e.g. while the GPU driver of a worker is reset. \
+    /// The consumers are cancelled on the broker, but their callbacks, the \
+    /// queues and the connection stay in place until `resume()` subscribes \
+    /// them again. Messages that were already sent before the pause are still \
+    /// handed to the callbacks
+    pub fn pause(&mut self) -> GenResult<()>{
+        if self.paused { return Ok(()); }
+        let tags: Vec<String> = self.consumers.keys().cloned().collect();
+        for tag in tags {
+            self.channel().basic_cancel(tag, false).map_err(BenderMqError::from)?;
+        }
+        self.paused = true;
+        Ok(())
+    }
+
+    /// Subscribe the consumers cancelled by `pause()` again. They keep their \
+    /// tags, so deliveries go to the same callbacks as before:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # use amqp::Table;
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.start_consumer("work", Table::new(), |delivery|{
+    ///     println!("Got {} bytes", delivery.body.len());
+    /// }).expect("Consuming from work failed");
+    /// connection.pause().expect("Couldn't pause");
+    /// // reset the GPU driver
+    /// connection.resume().expect("Couldn't resume");
+    /// connection.run_consumers().expect("Consuming from work failed");
+    /// ```
+    pub fn resume(&mut self) -> GenResult<()>{
+        if !self.paused { return Ok(()); }
+        self.reclaim_channel();
+        let channel = self.channel.as_mut().expect("The channel was lost while reading");
+        for (tag, &(ref queue, ref arguments, no_ack, exclusive)) in &self.consumers {
+            let consume = protocol::basic::Consume{
+                ticket: 0,
+                queue: queue.clone(),
+                consumer_tag: tag.clone(),
+                no_local: false,
+                no_ack,
+                exclusive,
+                nowait: false,
+                arguments: arguments.clone()
+            };
+            // The callback is still registered on the channel under this tag
+            let _: protocol::basic::ConsumeOk = channel.rpc(&consume, "basic.consume-ok").map_err(BenderMqError::from)?;
+        }
+        self.paused = false;
+        Ok(())
+    }
+
+    /// Returns true between `pause()` and `resume()`
+    pub fn is_paused(&self) -> bool{
+        self.paused
+    }
+
+    /// Put the channel into confirm mode. From now on the broker confirms \
+    /// every published message, which can be awaited with `wait_for_confirm()`
+    pub fn enable_confirms(&mut self) -> GenResult<()>{
+        if self.confirms.is_some() { return Ok(()); }
+        let _: protocol::confirm::SelectOk = self.channel().rpc(&protocol::confirm::Select{ nowait: false }, "confirm.select-ok").map_err(BenderMqError::from)?;
+        self.confirms = Some(Confirms::new());
+        Ok(())
+    }
+
+    /// The delivery tag the next publish will get in confirm mode
+    fn next_confirm_tag(&self) -> GenResult<u64>{
+        match self.confirms {
+            Some(ref confirms) => Ok(confirms.next_tag()),
+            None => Err(From::from("Confirm mode is not enabled, call enable_confirms() first"))
+        }
+    }
+
+    /// Serialize a job and post it to the `job` exchange like `post_job()`. \
+    /// Returns the serialized json together with the delivery tag the broker \
+    /// will confirm, so several jobs can be posted before awaiting each \
+    /// confirm with `wait_for_confirm()`:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # use std::time::Duration;
+    /// # fn example(jobs: &[bender_job::Job]){
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.enable_confirms().expect("Couldn't enable confirms");
+    /// let tags: Vec<u64> = jobs.iter()
+    ///     .map(|job| connection.post_job_confirmed(job).expect("Couldn't post job").1)
+    ///     .collect();
+    /// for tag in tags {
+    ///     let acked = connection.wait_for_confirm(tag, Duration::from_secs(5)).expect("No confirm");
+    ///     assert!(acked);
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn post_job_confirmed(&mut self, job: &Job) -> GenResult<(String, u64)>{
+        let tag = self.next_confirm_tag()?;
+        let json = self.post_job(job)?;
+        Ok((json, tag))
+    }
+
+    /// Serialize a task, post it to the `work` exchange like `post_task()` \
+    /// and wait until the broker confirmed it. Returns the serialized json \
+    /// together with the delivery tag the broker confirmed. Confirm mode is \
+    /// enabled if needed. No confirm within 30 seconds is a \
+    /// `BenderMqError::Timeout` error. \
+    /// If the `work` queue was declared on this connection with a \
+    /// `max_length` and `Overflow::RejectPublish` (see \
+    /// `create_work_queue_with_limit()`), a nack is how the broker pushes \
+    /// back and returns a `BenderMqError::QueueFull` error naming the `work` \
+    /// queue. Any other nack is a plain error. A scheduler can pause \
+    /// submitting instead of spinning:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Connection, BenderMQ, BenderMqError};
+    /// # use std::time::Duration;
+    /// # fn example(connection: &mut Connection, tasks: &[bender_job::task::Task]){
+    /// for task in tasks {
+    ///     while let Err(err) = connection.post_task_confirmed(task) {
+    ///         match err.downcast_ref::<BenderMqError>() {
+    ///             Some(&BenderMqError::QueueFull(ref queue)) => {
+    ///                 println!("Warning: {} is full, pausing submission", queue);
+    ///                 std::thread::sleep(Duration::from_secs(10));
+    ///             },
+    ///             _ => panic!("Couldn't post task: {}", err)
+    ///         }
+    ///     }
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn post_task_confirmed(&mut self, task: &Task) -> GenResult<(String, u64)>{
+        self.enable_confirms()?;
+        let tag = self.next_confirm_tag()?;
+        let json = self.post_task(task)?;
+        if !self.wait_for_confirm(tag, CONFIRM_TIMEOUT)? {
+            if self.reject_publish.contains("work") {
+                return Err(Box::new(BenderMqError::QueueFull("work".to_string())));
+            }
+            return Err(From::from(format!("The broker didn't accept the task with the delivery tag {}", tag)));
+        }
+        Ok((json, tag))
+    }
+
+    /// Serialize a job, post it to the `job` exchange like `post_job()` and \
+    /// wait until the broker confirmed it. Returns the serialized json \
+    /// together with the time from publishing to the confirm, which grows \
+    /// early when the broker gets overloaded. Confirm mode is enabled if \
+    /// needed. A nack or no confirm within 30 seconds is an error. With the \
+    /// `metrics` feature every measurement is also recorded in \
+    /// `publish_latency()`:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// # fn example(job: &bender_job::Job){
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// let (_, latency) = connection.timed_post_job(job).expect("Couldn't post job");
+    /// println!("The broker confirmed the job after {:?}", latency);
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn timed_post_job(&mut self, job: &Job) -> GenResult<(String, Duration)>{
+        self.enable_confirms()?;
+        let start = Instant::now();
+        let (json, tag) = self.post_job_confirmed(job)?;
+        if !self.wait_for_confirm(tag, CONFIRM_TIMEOUT)? {
+            return Err(From::from(format!("The broker didn't accept the job {}", job.id())));
+        }
+        let latency = start.elapsed();
+        #[cfg(feature = "metrics")]
+        self.publish_latency.observe(latency);
+        Ok((json, latency))
+    }
+
+    /// The publish to confirm latencies measured by `timed_post_job()`
+    #[cfg(feature = "metrics")]
+    pub fn publish_latency(&self) -> &Histogram{
+        &self.publish_latency
+    }
+
+    /// Wait until the broker confirmed the message with the given delivery \
+    /// tag. Returns true if it was acked and false if it was nacked. If no \
+    /// answer arrived within the timeout a `BenderMqError::Timeout` is \
+    /// returned.
+    pub fn wait_for_confirm(&mut self, tag: u64, timeout: Duration) -> GenResult<bool>{
+        self.next_confirm_tag()?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(acked) = self.confirms.as_mut().and_then(|confirms| confirms.take(tag)) {
+                return Ok(acked);
+            }
+            match self.next_frame_until(deadline)? {
+                Some(frame) => if let Some(ref mut confirms) = self.confirms {
+                    confirms.record(&frame)?;
+                },
+                None => return Err(Box::new(BenderMqError::Timeout))
+            }
+        }
+    }
+
+    /// Wait until the broker answered every message published in confirm \
+    /// mode or the timeout elapsed, without closing anything. Returns the \
+    /// delivery tags that were nacked (and not awaited with \
+    /// `wait_for_confirm()` already) and the ones still unconfirmed. The \
+    /// nacks are forgotten once they have been reported. Outside of confirm \
+    /// mode this returns right away with a clean report
+    pub fn drain_confirms(&mut self, timeout: Duration) -> GenResult<ConfirmReport>{
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.confirms {
+                Some(ref confirms) if confirms.pending() > 0 => {},
+                _ => break
+            }
+            match self.next_frame_until(deadline)? {
+                Some(frame) => if let Some(ref mut confirms) = self.confirms {
+                    confirms.record(&frame)?;
+                },
+                None => break
+            }
+        }
+        Ok(match self.confirms {
+            Some(ref mut confirms) => ConfirmReport{ nacked: confirms.take_nacked(), unconfirmed: confirms.outstanding() },
+            None => ConfirmReport::default()
+        })
+    }
+
+    /// Drain the confirms like `drain_confirms()`, then close the channel \
+    /// and the session. The connection is closed even if confirms are \
+    /// missing, the report tells which messages can't be counted on, e.g. \
+    /// to publish them again after a restart:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, RoutingKey};
+    /// # use std::time::Duration;
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.enable_confirms().expect("Couldn't enable confirms");
+    /// let routing_key = RoutingKey::new("status").expect("Invalid routing key");
+    /// connection.post_to_info(routing_key, "shutting down").expect("Couldn't post to info");
+    /// let report = connection.close_draining(Duration::from_secs(10)).expect("Couldn't close the connection");
+    /// if !report.is_clean() {
+    ///     println!("Warning: {} messages were refused, {} weren't confirmed", report.nacked.len(), report.unconfirmed.len());
+    /// }
+    /// ```
+    pub fn close_draining(mut self, timeout: Duration) -> GenResult<ConfirmReport>{
+        let report = self.drain_confirms(timeout)?;
+        self.close()?;
+        Ok(report)
+    }
+
+    /// Wait until the broker confirmed every message published in confirm \
+    /// mode, then close the channel and the session. If the confirms don't \
+    /// arrive within the timeout the wait ends there, the connection is \
+    /// closed and the number of unconfirmed messages is returned. The timeout \
+    /// only bounds the wait for confirms: closing still waits for the broker \
+    /// to answer the close. Nacked messages are logged, use \
+    /// `close_draining()` to get their delivery tags:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, CloseOutcome, RoutingKey};
+    /// # use std::time::Duration;
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.enable_confirms().expect("Couldn't enable confirms");
+    /// let routing_key = RoutingKey::new("status").expect("Invalid routing key");
+    /// connection.post_to_info(routing_key, "shutting down").expect("Couldn't post to info");
+    /// match connection.close_gracefully(Duration::from_secs(10)).expect("Couldn't close the connection") {
+    ///     CloseOutcome::Clean => println!("Bye"),
+    ///     CloseOutcome::PendingAcks(n) => println!("Warning: {} messages weren't confirmed", n)
+    /// }
+    /// ```
+    pub fn close_gracefully(self, timeout: Duration) -> GenResult<CloseOutcome>{
+        let report = self.close_draining(timeout)?;
+        if !report.nacked.is_empty() {
+            println!("Warning: The broker refused the messages {:?} before closing", report.nacked);
+        }
+        if report.unconfirmed.is_empty() {
+            Ok(CloseOutcome::Clean)
+        } else {
+            Ok(CloseOutcome::PendingAcks(report.unconfirmed.len()))
+        }
+    }
+
+    /// Read the next frame like `next_frame()`, but give up once the deadline \
+    /// has passed and return None. The amqp crate can't interrupt a blocking \
+    /// read, so the read happens on a thread that owns the channel until its \
+    /// frame arrives. If the deadline passes first, the channel stays with \
+    /// that thread. Until its frame arrives publishing fails with a \
+    /// `BenderMqError::Timeout` error and any other use of the channel waits \
+    /// for the frame
+    fn next_frame_until(&mut self, deadline: Instant) -> GenResult<Option<Frame>>{
+        loop {
+            let now = Instant::now();
+            if now >= deadline { return Ok(None); }
+            let reader = match self.pending_read.take() {
+                Some(reader) => reader,
+                None => {
+                    let mut channel = match self.channel.take() {
+                        Some(channel) => channel,
+                        None => return Err(From::from("The channel was lost while reading"))
+                    };
+                    let (sender, reader) = mpsc::channel();
+                    thread::spawn(move || {
+                        let frame = channel.read();
+                        let _ = sender.send((channel, frame));
+                    });
+                    reader
+                }
+            };
+            match reader.recv_timeout(deadline - now) {
+                Ok((channel, frame)) => {
+                    self.channel = Some(channel);
+                    let frame = frame.map_err(BenderMqError::from)?;
+                    match flow::intercept(self.channel(), frame)? {
+                        Intercepted::Frame(frame) => return Ok(Some(frame)),
+                        Intercepted::Flow(blocked) => self.flow_blocked = blocked
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    self.pending_read = Some(reader);
+                    return Ok(None);
+                },
+                Err(RecvTimeoutError::Disconnected) => return Err(From::from("The channel was lost while reading"))
+            }
+        }
+    }
+
+    /// Wait for a read that outlived its deadline and take the channel back
+    fn reclaim_channel(&mut self){
+        if let Some(reader) = self.pending_read.take() {
+            if let Ok((channel, frame)) = reader.recv() {
+                self.restore_channel(channel, frame);
+            }
+        }
+    }
+
+    /// Take the channel back if a read that outlived its deadline has \
+    /// finished by now, without waiting for it
+    fn poll_read(&mut self){
+        let finished = match self.pending_read {
+            Some(ref reader) => reader.try_recv().ok(),
+            None => None
+        };
+        if let Some((channel, frame)) = finished {
+            self.pending_read = None;
+            self.restore_channel(channel, frame);
+        }
+    }
+
+    /// Fail instead of publishing while the broker applies flow control \
+    /// (`BenderMqError::FlowBlocked`) or while a read that outlived its \
+    /// deadline still holds the channel (`BenderMqError::Timeout`). The \
+    /// latter keeps a retried publish from blocking until that frame arrives
+    fn check_publishable(&mut self) -> GenResult<()>{
+        self.poll_read();
+        if self.pending_read.is_some() { return Err(Box::new(BenderMqError::Timeout)); }
+        if self.flow_blocked { return Err(Box::new(BenderMqError::FlowBlocked)); }
+        Ok(())
+    }
+
+    /// Put the channel of a finished read back and record what the read \
+    /// brought along: a `channel.flow` state or a confirm. Anything else is \
+    /// dropped like while draining the confirms
+    fn restore_channel(&mut self, mut channel: Channel, frame: Result<Frame, AMQPError>){
+        let intercepted = match frame {
+            Ok(frame) => flow::intercept(&mut channel, frame),
+            Err(err) => Err(From::from(BenderMqError::from(err)))
+        };
+        self.channel = Some(channel);
+        match intercepted {
+            Ok(Intercepted::Frame(frame)) => self.record_frame(&frame),
+            Ok(Intercepted::Flow(blocked)) => self.flow_blocked = blocked,
+            Err(err) => println!("Error: Couldn't read from the channel: {}", err)
+        }
+    }
+
+    /// Count the ready messages of the `info`, `job`, `work` and `worker` \
+    /// queues in one go. A queue that doesn't exist is reported with 0 \
+    /// messages. The counting happens on a separate channel, as the broker \
+    /// closes the channel that asked for a missing queue:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// let depths = connection.all_queue_depths().expect("Couldn't count messages");
+    /// println!("{} tasks are waiting", depths["work"]);
+    /// ```
+    pub fn all_queue_depths(&mut self) -> GenResult<HashMap<String, u32>>{
+        let mut depths = HashMap::new();
+        let mut probe = None;
+        let mut probe_id = PROBE_CHANNEL;
+        for queue in KNOWN_QUEUES.iter() {
+            let mut channel = match probe.take() {
+                Some(channel) => channel,
+                None => self.session.open_channel(probe_id).map_err(BenderMqError::from)?
+            };
+            match queue::message_count(&mut channel, queue)? {
+                Some(count) => {
+                    depths.insert(queue.to_string(), count);
+                    probe = Some(channel);
+                },
+                // The broker closed the channel, the next queue needs a new one
+                None => {
+                    depths.insert(queue.to_string(), 0);
+                    probe_id += 1;
+                }
+            }
+        }
+        if let Some(mut channel) = probe {
+            channel.close(REPLY_SUCCESS, "Bye").map_err(BenderMqError::from)?;
+        }
+        Ok(depths)
+    }
+
+    /// Ask the broker for its product, version and capabilities, so features \
+    /// that need a recent broker can be checked before they are used. This \
+    /// opens (and right away closes) a second TCP connection to the host \
+    /// and port this connection was opened with:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ};
+    /// let connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// let broker = connection.server_capabilities().expect("Couldn't read the server properties");
+    /// broker.require_version(3, 8, "quorum queues").expect("The broker is too old");
+    /// if !broker.supports("consumer_priorities") {
+    ///     println!("Warning: The broker ignores consumer priorities");
+    /// }
+    /// ```
+    pub fn server_capabilities(&self) -> GenResult<Capabilities>{
+        capabilities::read(self.options.host.as_str(), self.options.port)
+    }
+
+    /// Close the channel and then the session. If a read that outlived its \
+    /// deadline still holds the channel, only the session is closed, which \
+    /// closes the channel on the broker as well
+    pub fn close(mut self) -> GenResult<()>{
+        if self.pending_read.is_none() {
+            self.channel().close(REPLY_SUCCESS, "Bye").map_err(BenderMqError::from)?;
+        }
+        self.session.close(REPLY_SUCCESS, "Good Bye");
+        Ok(())
+    }
+}
+
+
+impl BenderMQ for Connection{
+    /// Open a AMPQ session and a channel on it
+    fn open_channel<S>(url: S) -> GenResult<Self> where S: Into<String>{
+        Connection::open_url(url.into().as_str())
+    }
+
+    /// Open a AMPQ session and a channel on it to the default URL specified \
+    /// in the config. A missing or broken config is returned as an error.
+    fn open_default_channel() -> GenResult<Self>{
+        let url = session::url_from_config()?;
+        Connection::open_url(url.as_str())
+    }
+
+    /// Open a AMPQ session and a channel on it, retrying with backoff
+    fn open_channel_with_backoff<S>(url: S, backoff: &BackoffConfig, attempts: u32) -> GenResult<Self> where S: Into<String>{
+        let url = url.into();
+        let mut attempt = 0;
+        loop {
+            match Connection::open_url(url.as_str()) {
+                Ok(connection) => return Ok(connection),
+                Err(err) => {
+                    attempt += 1;
+                    if attempt >= attempts { return Err(err); }
+                    let delay = backoff.delay(attempt - 1);
+                    println!("Warning: Couldn't connect to {} ({}), retrying in {:?}", url, err, delay);
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+    }
+
+    /// The exchange set with `set_work_exchange()`
+    fn work_exchange(&self) -> String{
+        self.work_exchange.clone()
+    }
+
+    /// The strategy set with `set_routing_strategy()`
+    fn routing_strategy(&self) -> &RoutingStrategy{
+        &*self.routing_strategy
+    }
+
+    /// The flag set with `set_lazy_work_queue()`
+    fn lazy_work_queue(&self) -> bool{
+        self.lazy_work_queue
+    }
+
+    /// The profile set with `set_serialization_profile()`
+    fn serialization_profile(&self) -> SerializationProfile{
+        self.serialization_profile
+    }
+
+    /// The flags set with `set_publish_options()`
+    fn publish_options(&self) -> PublishOptions{
+        self.publish_options
+    }
+
+    /// The state of the last `channel.flow` frame read from the channel. \
+    /// Frames only arrive through reads (consumers, confirms and answers the \
+    /// post methods wait for), a producer that does none of these doesn't \
+    /// learn about flow control
+    fn is_flow_blocked(&self) -> bool{
+        self.flow_blocked
+    }
+
+    /// Publish a message on the channel with the default properties merged \
+    /// in and keep track of its delivery tag when in confirm mode
+    fn publish_with(&mut self, exchange: &str, routing_key: &str, properties: BasicProperties, message: Vec<u8>, options: PublishOptions) -> GenResult<()>{
+        self.check_publishable()?;
+        let properties = match self.default_properties {
+            Some(ref defaults) => properties::merge(properties, defaults),
+            None => properties
+        };
+        self.check_exchange(exchange)?;
+        self.channel().publish_with(exchange, routing_key, properties, message, options)?;
+        if let Some(ref mut confirms) = self.confirms {
+            confirms.published();
+        }
+        Ok(())
+    }
+
+    /// Publish a message with exactly the given properties, without the \
+    /// default properties, and keep track of its delivery tag when in \
+    /// confirm mode
+    fn publish_raw<S, U>(&mut self, exchange: S, routing_key: S, properties: BasicProperties, body: U) -> GenResult<()> where S: Into<String>, U: Into<Vec<u8>>{
+        self.check_publishable()?;
+        let exchange = exchange.into();
+        self.check_exchange(exchange.as_str())?;
+        let options = self.publish_options;
+        let routing_key = routing_key.into();
+        self.channel().publish_with(exchange.as_str(), routing_key.as_str(), properties, body.into(), options)?;
+        if let Some(ref mut confirms) = self.confirms {
+            confirms.published();
+        }
+        Ok(())
+    }
+
+    /// Set the prefetch on the channel and remember it for `reconnect()`
+    fn set_prefetch(&mut self, count: u16, size: Option<u32>) -> GenResult<()>{
+        self.channel().set_prefetch(count, size)?;
+        self.prefetch = Some((count, size));
+        Ok(())
+    }
+
+    /// Set the shared prefetch on the channel and remember it for \
+    /// `reconnect()`
+    fn set_prefetch_global(&mut self, count: u16) -> GenResult<()>{
+        self.channel().set_prefetch_global(count)?;
+        self.prefetch_global = Some(count);
+        Ok(())
+    }
+
+    /// The channel that was opened on the session
+    fn channel(&mut self) -> &mut Channel{
+        self.reclaim_channel();
+        self.channel.as_mut().expect("The channel was lost while reading")
+    }
+}
+
+
+impl Hooks for Connection{
+    /// Returns true if the exchange or queue was declared on this connection
+    fn is_declared(&self, key: &str) -> bool{
+        self.declared.contains(key)
+    }
+
+    /// Remember that the exchange or queue was declared on this connection
+    fn record_declared(&mut self, key: &str){
+        self.declared.insert(key);
+    }
+
+    /// Remember the queues that refuse messages once they are full, so \
+    /// `post_task_confirmed()` can tell a full queue from any other nack
+    fn record_overflow(&mut self, queue: &str, overflow: Overflow){
+        match overflow {
+            Overflow::RejectPublish => { self.reject_publish.insert(queue.to_string()); },
+            Overflow::DropHead => { self.reject_publish.remove(queue); }
+        }
+    }
+
+    /// Keep track of the confirms that arrived while waiting for something \
+    /// else
+    fn record_frame(&mut self, frame: &Frame){
+        if let Some(ref mut confirms) = self.confirms {
+            if let Err(err) = confirms.record(frame) {
+                println!("Error: Couldn't read a confirm: {}", err);
+            }
+        }
+    }
+
+    /// Remember the consumer so `pause()` and `resume()` can reach it
+    fn record_consumer(&mut self, consumer_tag: &str, queue: &str, arguments: &Table, no_ack: bool, exclusive: bool){
+        self.consumers.insert(consumer_tag.to_string(), (queue.to_string(), arguments.clone(), no_ack, exclusive));
+    }
+
+    /// Remember the `channel.flow` state for `is_flow_blocked()`
+    fn record_flow(&mut self, blocked: bool){
+        self.flow_blocked = blocked;
+    }
+
+    /// Publish in confirm mode, enabling it if needed, and wait for the \
+    /// confirm of every message
+    fn publish_confirmed(&mut self, routing_key: &str, messages: Vec<(BasicProperties, Vec<u8>)>) -> GenResult<()>{
+        self.enable_confirms()?;
+        let options = PublishOptions{ mandatory: false, immediate: false, content_hash: false };
+        let mut tags = Vec::with_capacity(messages.len());
+        for (properties, body) in messages {
+            self.check_publishable()?;
+            tags.push(self.next_confirm_tag()?);
+            self.channel().publish_with("", routing_key, properties, body, options)?;
+            if let Some(ref mut confirms) = self.confirms {
+                confirms.published();
+            }
+        }
+        for tag in tags {
+            if !self.wait_for_confirm(tag, CONFIRM_TIMEOUT)? {
+                return Err(From::from(format!("The broker didn't accept every message moved to {}", routing_key)));
+            }
+        }
+        Ok(())
+    }
+}
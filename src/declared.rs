@@ -0,0 +1,50 @@
+//! Bookkeeping of the exchanges and queues a connection already declared
+
+use std::collections::HashSet;
+
+
+/// The keys of everything declared during the lifetime of a connection
+#[derive(Debug, Clone)]
+pub struct Declared{
+    enabled: bool,
+    keys: HashSet<String>
+}
+
+
+impl Default for Declared{
+    fn default() -> Self{
+        Declared{
+            enabled: true,
+            keys: HashSet::new()
+        }
+    }
+}
+
+
+impl Declared{
+    /// Returns true if caching is enabled and the key has been recorded, so \
+    /// the declaration can be skipped
+    pub fn contains(&self, key: &str) -> bool{
+        self.enabled && self.keys.contains(key)
+    }
+
+    /// Returns true if the key has been recorded, regardless of caching
+    pub fn recorded(&self, key: &str) -> bool{
+        self.keys.contains(key)
+    }
+
+    /// Record a successful declaration
+    pub fn insert(&mut self, key: &str){
+        self.keys.insert(key.to_string());
+    }
+
+    /// Turn skipping of recorded declarations on or off
+    pub fn set_enabled(&mut self, enabled: bool){
+        self.enabled = enabled;
+    }
+
+    /// Forget all recorded declarations
+    pub fn clear(&mut self){
+        self.keys.clear();
+    }
+}
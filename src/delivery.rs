@@ -0,0 +1,165 @@
+//! A message that was delivered to one of our consumers
+
+use amqp::TableEntry;
+use amqp::protocol::basic::{BasicProperties, Deliver, GetOk};
+use serde::de::DeserializeOwned;
+use error::BenderMqError;
+use integrity;
+use GenResult;
+
+
+/// The `content_encoding` of a body that is stored as it is
+const IDENTITY: &str = "identity";
+
+/// The header in which bender_mq counts how often it requeued a message
+pub const REDELIVERY_COUNT_HEADER: &str = "x-redelivery-count";
+
+
+/// A single message as it was delivered by the broker, containing the body \
+/// together with the routing information and the message properties
+#[derive(Debug, Clone)]
+pub struct Delivery{
+    /// The tag the broker uses to identify this delivery on the channel
+    pub delivery_tag: u64,
+    /// True if this message has been delivered before and wasn't acknowledged
+    pub redelivered: bool,
+    /// The exchange the message was originally published to
+    pub exchange: String,
+    /// The routing key the message was published with
+    pub routing_key: String,
+    /// The properties (content type, headers, ...) of the message
+    pub properties: BasicProperties,
+    /// The raw message body
+    pub body: Vec<u8>
+}
+
+
+impl Delivery{
+    /// Create a Delivery from the parts the amqp crate hands to a consumer
+    pub fn new(deliver: Deliver, properties: BasicProperties, body: Vec<u8>) -> Self{
+        Delivery{
+            delivery_tag: deliver.delivery_tag,
+            redelivered: deliver.redelivered,
+            exchange: deliver.exchange,
+            routing_key: deliver.routing_key,
+            properties,
+            body
+        }
+    }
+
+    /// Create a Delivery from a message fetched with `basic_get`
+    pub fn from_get(get_ok: GetOk, properties: BasicProperties, body: Vec<u8>) -> Self{
+        Delivery{
+            delivery_tag: get_ok.delivery_tag,
+            redelivered: get_ok.redelivered,
+            exchange: get_ok.exchange,
+            routing_key: get_ok.routing_key,
+            properties,
+            body
+        }
+    }
+
+    /// The AMQP `type` property of the message (see `post_typed()`), \
+    /// which tells consumers of a queue carrying several kinds of messages \
+    /// what this one is:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::Delivery;
+    /// # fn example(delivery: &Delivery){
+    /// match delivery.message_type() {
+    ///     Some("render") => println!("Rendering"),
+    ///     Some("cancel") => println!("Canceling"),
+    ///     _ => println!("Warning: Ignoring message of unknown type")
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn message_type(&self) -> Option<&str>{
+        self.properties._type.as_ref().map(|message_type| message_type.as_str())
+    }
+
+    /// How often this message has been handed out before, the highest of: \
+    /// the `x-redelivery-count` header bender_mq maintains when requeueing, \
+    /// the `x-delivery-count` header of quorum queues and the sum of the \
+    /// counts in the `x-death` header the broker adds when dead-lettering. \
+    /// The `redelivered` flag alone can't tell a first from a hundredth retry
+    pub fn redelivery_count(&self) -> u64{
+        let headers = match self.properties.headers {
+            Some(ref headers) => headers,
+            None => return 0
+        };
+        let counted = headers.get(REDELIVERY_COUNT_HEADER).and_then(count).unwrap_or(0);
+        let delivered = headers.get("x-delivery-count").and_then(count).unwrap_or(0);
+        let died = match headers.get("x-death") {
+            Some(&TableEntry::FieldArray(ref deaths)) => deaths.iter()
+                .filter_map(|death| match *death {
+                    TableEntry::FieldTable(ref death) => death.get("count").and_then(count),
+                    _ => None
+                })
+                .sum(),
+            _ => 0
+        };
+        counted.max(delivered).max(died)
+    }
+
+    /// The position of this message in a stream queue, which the broker \
+    /// adds as the `x-stream-offset` header to every message it delivers \
+    /// from a stream. None for deliveries from any other queue
+    pub fn stream_offset(&self) -> Option<u64>{
+        self.properties.headers.as_ref()
+            .and_then(|headers| headers.get("x-stream-offset"))
+            .and_then(count)
+    }
+
+    /// Whether the body matches the `content-hash` header it was published \
+    /// with (see `verify_content_hash()`), None if it has none
+    pub fn verify_content_hash(&self) -> Option<bool>{
+        integrity::verify_content_hash(&self.properties, &self.body)
+    }
+
+    /// Deserialize the json body. Bodies with a `content_encoding` other \
+    /// than `identity` (e.g. `zstd`) return a \
+    /// `BenderMqError::UnsupportedEncoding` error instead of being read as \
+    /// json, decode those from `body` yourself:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{BenderMqError, Delivery};
+    /// # fn example(delivery: &Delivery){
+    /// match delivery.json::<Vec<String>>() {
+    ///     Ok(values) => println!("Got {} values", values.len()),
+    ///     Err(err) => println!("Error: {}", err)
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    pub fn json<T>(&self) -> GenResult<T> where T: DeserializeOwned{
+        decode_json(&self.properties, &self.body)
+    }
+}
+
+
+/// Read a header value that holds a count
+fn count(entry: &TableEntry) -> Option<u64>{
+    match *entry {
+        TableEntry::ShortShortUint(n) => Some(u64::from(n)),
+        TableEntry::ShortUint(n) => Some(u64::from(n)),
+        TableEntry::LongUint(n) => Some(u64::from(n)),
+        TableEntry::LongLongUint(n) => Some(n),
+        TableEntry::ShortShortInt(n) if n >= 0 => Some(n as u64),
+        TableEntry::ShortInt(n) if n >= 0 => Some(n as u64),
+        TableEntry::LongInt(n) if n >= 0 => Some(n as u64),
+        TableEntry::LongLongInt(n) if n >= 0 => Some(n as u64),
+        _ => None
+    }
+}
+
+
+/// Deserialize a json body, unless its `content_encoding` says it is encoded
+pub fn decode_json<T>(properties: &BasicProperties, body: &[u8]) -> GenResult<T> where T: DeserializeOwned{
+    match properties.content_encoding {
+        Some(ref encoding) if !encoding.is_empty() && encoding != IDENTITY => {
+            Err(Box::new(BenderMqError::UnsupportedEncoding(encoding.clone())))
+        },
+        _ => Ok(serde_json::from_slice(body)?)
+    }
+}
@@ -0,0 +1,140 @@
+//! Error types that are specific to bender_mq. They are returned boxed as a \
+//! `GenError`, so callers that care about the cause can downcast them:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::BenderMqError;
+//! # let err: Box<std::error::Error> = Box::new(BenderMqError::FlowBlocked);
+//! if let Some(BenderMqError::FlowBlocked) = err.downcast_ref::<BenderMqError>(){
+//!     // back off and try again later
+//! }
+//! ```
+
+use std::fmt;
+use std::error::Error;
+use std::time::Duration;
+use amqp::AMQPError;
+use GenError;
+
+
+/// Errors returned by the methods of the [BenderMQ](trait.BenderMQ.html) trait
+#[derive(Debug)]
+pub enum BenderMqError{
+    /// The broker paused publishing on this channel via `channel.flow`. \
+    /// Nothing has been published, the message can be retried later
+    FlowBlocked,
+    /// The broker cancelled the consumer with the given tag, e.g. because its \
+    /// queue has been deleted. The queue has to be declared and consumed again
+    ConsumerCancelled(String),
+    /// The broker didn't answer in time
+    Timeout,
+    /// A message was about to be published to an exchange that hasn't been \
+    /// declared on this connection. Publishing it would make the broker close \
+    /// the channel
+    ExchangeNotDeclared(String),
+    /// The queue with the given name already exists with other arguments \
+    /// (e.g. another `x-message-ttl`). The broker closed the channel, the \
+    /// queue has to be deleted before it can be declared with the new ones
+    QueueArgumentsMismatch(String, String),
+    /// The message body is encoded (e.g. compressed) with the given \
+    /// `content_encoding`, which this crate can't decode. Read the raw body \
+    /// and decode it yourself
+    UnsupportedEncoding(String),
+    /// The user isn't allowed to publish to the exchange with the given \
+    /// name. The broker closed the channel, the reason is the broker's text
+    AccessRefused(String, String),
+    /// The broker refused an exclusive consumer on the queue with the given \
+    /// name, because another consumer (e.g. another instance) is already \
+    /// subscribed to it. The broker closed the channel
+    QueueInUse(String),
+    /// The broker refused a message for the queue with the given name, \
+    /// because the queue reached its `x-max-length` and rejects new \
+    /// messages. Nothing has been published, the message can be retried \
+    /// once consumers made room
+    QueueFull(String),
+    /// A `RateLimitedPublisher` is over its rate. Nothing has been \
+    /// published, the message can be retried after the given duration
+    RateLimited(Duration),
+    /// A step of `connect_and_setup()` (connecting or one of the declares, \
+    /// as named by the first value) failed with the given error
+    SetupFailed(String, GenError),
+    /// The feature named by the first value needs a newer broker (the \
+    /// second value, e.g. `RabbitMQ 3.8+`) than the one connected to (the \
+    /// third value, its version). See `Connection::server_capabilities()`
+    UnsupportedBroker(String, String, String),
+    /// The underlying amqp crate returned an error
+    Amqp(AMQPError)
+}
+
+
+impl BenderMqError{
+    /// Returns true if the failed operation may succeed when retried later. \
+    /// Of the errors of the amqp crate only the io errors of a lost \
+    /// connection are, everything else would fail the same way again
+    pub fn is_retryable(&self) -> bool{
+        match *self{
+            BenderMqError::FlowBlocked => true,
+            BenderMqError::ConsumerCancelled(_) => false,
+            BenderMqError::Timeout => true,
+            BenderMqError::ExchangeNotDeclared(_) => false,
+            BenderMqError::QueueArgumentsMismatch(_, _) => false,
+            BenderMqError::UnsupportedEncoding(_) => false,
+            BenderMqError::AccessRefused(_, _) => false,
+            BenderMqError::QueueInUse(_) => false,
+            BenderMqError::QueueFull(_) => true,
+            BenderMqError::RateLimited(_) => true,
+            BenderMqError::SetupFailed(_, ref err) => is_retryable(err),
+            BenderMqError::UnsupportedBroker(_, _, _) => false,
+            BenderMqError::Amqp(AMQPError::IoError(_)) => true,
+            BenderMqError::Amqp(_) => false
+        }
+    }
+}
+
+
+/// Returns true if the error is a `BenderMqError` that may go away when \
+/// retried (see `BenderMqError::is_retryable()`). Any other error, e.g. a \
+/// job that doesn't serialize, is permanent
+pub fn is_retryable(err: &GenError) -> bool{
+    match err.downcast_ref::<BenderMqError>() {
+        Some(err) => err.is_retryable(),
+        None => false
+    }
+}
+
+
+impl fmt::Display for BenderMqError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            BenderMqError::FlowBlocked => write!(f, "Publishing is paused by the broker (channel.flow)"),
+            BenderMqError::ConsumerCancelled(ref tag) => write!(f, "The broker cancelled the consumer {}", tag),
+            BenderMqError::Timeout => write!(f, "The broker didn't answer in time"),
+            BenderMqError::ExchangeNotDeclared(ref exchange) => write!(f, "exchange '{}' not declared", exchange),
+            BenderMqError::QueueArgumentsMismatch(ref queue, ref reason) => write!(f, "queue '{}' already exists with different arguments, delete it to change them ({})", queue, reason),
+            BenderMqError::UnsupportedEncoding(ref encoding) => write!(f, "the message body has the unsupported content encoding '{}'", encoding),
+            BenderMqError::AccessRefused(ref exchange, ref reason) => write!(f, "publishing to exchange '{}' is not allowed ({})", exchange, reason),
+            BenderMqError::QueueInUse(ref queue) => write!(f, "queue '{}' is in exclusive use by another consumer", queue),
+            BenderMqError::QueueFull(ref queue) => write!(f, "queue '{}' is full and rejected the message", queue),
+            BenderMqError::RateLimited(ref wait) => write!(f, "the publish rate limit is exceeded, retry in {:?}", wait),
+            BenderMqError::SetupFailed(ref step, ref err) => write!(f, "{} failed: {}", step, err),
+            BenderMqError::UnsupportedBroker(ref feature, ref required, ref found) => write!(f, "{} require {} (the broker is {})", feature, required, found),
+            BenderMqError::Amqp(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+
+impl Error for BenderMqError{
+    fn source(&self) -> Option<&(Error + 'static)>{
+        match *self{
+            BenderMqError::SetupFailed(_, ref err) => Some(&**err),
+            _ => None
+        }
+    }
+}
+
+
+impl From<AMQPError> for BenderMqError{
+    fn from(err: AMQPError) -> Self{
+        BenderMqError::Amqp(err)
+    }
+}
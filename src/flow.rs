@@ -0,0 +1,34 @@
+//! Answers the `channel.flow` frames the broker sends. The amqp crate \
+//! passes these frames on without acting on them, so every frame bender_mq \
+//! reads from a channel is handed to `intercept()` first.
+//!
+//! The state itself belongs to the channel it was announced on and is kept \
+//! by the `BenderMQ` implementor through `Hooks::record_flow()`. A bare `Channel` \
+//! has nowhere to keep it, a `Connection` does.
+
+use amq_proto::Frame;
+use amqp::{Channel, protocol};
+use frames;
+use GenResult;
+
+
+/// What `intercept()` made of a frame
+pub enum Intercepted{
+    /// Any frame other than `channel.flow`, handed back untouched
+    Frame(Frame),
+    /// A `channel.flow` frame that has been answered. True if the broker \
+    /// asked us to stop publishing
+    Flow(bool)
+}
+
+
+/// Inspect a frame read from the channel. If it is a `channel.flow` frame the \
+/// broker gets its `channel.flow-ok` and the new state is returned. Every \
+/// other frame is handed back to the caller.
+pub fn intercept(channel: &mut Channel, frame: Frame) -> GenResult<Intercepted>{
+    if frames::method_name(&frame) != Some("channel.flow") { return Ok(Intercepted::Frame(frame)); }
+
+    let flow: protocol::channel::Flow = frames::decode(&frame)?;
+    channel.send_method_frame(&protocol::channel::FlowOk{ active: flow.active })?;
+    Ok(Intercepted::Flow(!flow.active))
+}
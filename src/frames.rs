@@ -0,0 +1,18 @@
+//! Helpers to look into the frames the amqp crate hands back to us when \
+//! reading from a channel.
+
+use amq_proto::{Frame, Method, MethodFrame};
+use GenResult;
+
+
+/// Returns the name (e.g. `basic.cancel`) of a method frame or None for \
+/// every other kind of frame
+pub fn method_name(frame: &Frame) -> Option<&'static str>{
+    MethodFrame::decode(frame).ok().map(|method_frame| method_frame.method_name())
+}
+
+
+/// Decode a method frame into the method it carries
+pub fn decode<T>(frame: &Frame) -> GenResult<T> where T: Method{
+    Ok(T::decode(MethodFrame::decode(frame)?)?)
+}
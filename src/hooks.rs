@@ -0,0 +1,53 @@
+//! The bookkeeping the `BenderMQ` methods report to, e.g. which exchanges \
+//! and queues are declared or whether the broker paused publishing. It is \
+//! kept out of the public trait, so nobody outside the crate can fake a \
+//! declaration or the flow state. As this module is private the trait can't \
+//! be named outside the crate, which also means `BenderMQ` (which builds on \
+//! it) is only implemented here, by `Channel` and `Connection`.
+
+use amq_proto::Frame;
+use amqp::Table;
+use amqp::protocol::basic::BasicProperties;
+use queue::Overflow;
+use GenResult;
+
+
+/// Callbacks of the `BenderMQ` methods. Every method with a default does \
+/// nothing, which is all a bare `Channel` can do. A `Connection` keeps the \
+/// state
+pub trait Hooks{
+    /// Returns true if the exchange or queue with the given key (e.g. \
+    /// `exchange info-topic` or `queue work`) has already been declared and \
+    /// the declare methods can skip it. Implementors that don't keep track \
+    /// of their declarations always declare again
+    fn is_declared(&self, _key: &str) -> bool{
+        false
+    }
+
+    /// Called by the declare methods after an exchange or queue has been \
+    /// declared successfully
+    fn record_declared(&mut self, _key: &str){}
+
+    /// Called by `create_work_queue_with_limit()` with the overflow behaviour \
+    /// of the queue it declared with a `max_length`
+    fn record_overflow(&mut self, _queue: &str, _overflow: Overflow){}
+
+    /// Called by the consume methods after a consumer has been registered \
+    /// with the broker
+    fn record_consumer(&mut self, _consumer_tag: &str, _queue: &str, _arguments: &Table, _no_ack: bool, _exclusive: bool){}
+
+    /// Called with the frames read while waiting for a specific answer (see \
+    /// `post_task_routed_check()`) that weren't that answer, e.g. confirms
+    fn record_frame(&mut self, _frame: &Frame){}
+
+    /// Called with the state of every `channel.flow` frame read from the \
+    /// channel, true if the broker asked us to stop publishing. \
+    /// Implementors that keep it report it with `is_flow_blocked()`
+    fn record_flow(&mut self, _blocked: bool){}
+
+    /// Publish the messages to the default exchange with the given routing \
+    /// key in confirm mode and return once the broker acked every one of \
+    /// them. Used by `redeclare_queue_migrating()`, so the messages it moves \
+    /// are only acknowledged once they are safe in the other queue
+    fn publish_confirmed(&mut self, routing_key: &str, messages: Vec<(BasicProperties, Vec<u8>)>) -> GenResult<()>;
+}
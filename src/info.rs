@@ -0,0 +1,140 @@
+//! Typed events for the messages on the `info-topic` exchange. Which struct \
+//! a message contains is decided by its routing key:
+//! - `job.<id>.tasks` carries a json array of tasks (see `post_tasks_info()`)
+//! - keys ending in `delta` carry a merge patch of a job (see \
+//!   `post_job_delta()` and `apply_merge_patch()`)
+//! - any key containing the word `task` carries a single task, e.g. \
+//!   `<job_id>.<engine>.<frame>.task` used by `update_and_post_task()`
+//! - everything else (e.g. the plain job id used by `post_job_info()` or \
+//!   `job.<id>.complete` used by `post_job_complete()`) carries a job
+//!
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::parse_info_event;
+//! // Bodies that don't match the struct for their key are an error
+//! assert!(parse_info_event("job.123.tasks", b"{}").is_err());
+//! ```
+
+use std::io::BufRead;
+use serde_json::Value;
+use bender_job::task::Task;
+use bender_job::Job;
+use GenResult;
+
+
+/// A deserialized message from the `info-topic` exchange
+#[derive(Debug)]
+pub enum InfoEvent{
+    /// A job has been updated
+    JobUpdate(Job),
+    /// A single task has been updated
+    TaskUpdate(Task),
+    /// Several tasks of a job have been updated at once
+    TaskUpdates(Vec<Task>),
+    /// Only the fields of a job that changed, as a json merge patch
+    JobPatch(Value)
+}
+
+
+/// How a job ended, posted by `post_job_complete()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus{
+    /// All tasks of the job have been rendered
+    Finished,
+    /// The job can't be finished
+    Failed,
+    /// The job has been cancelled before it was finished
+    Canceled
+}
+
+
+impl JobStatus{
+    /// The word that is embedded as `completion_status` into the job update
+    pub fn as_str(&self) -> &'static str{
+        match *self{
+            JobStatus::Finished => "finished",
+            JobStatus::Failed => "failed",
+            JobStatus::Canceled => "canceled"
+        }
+    }
+}
+
+
+/// Read the status `post_job_complete()` embedded into a job update, as \
+/// `completionStatus` if it was posted with `SerializationProfile::CamelCase`
+pub fn completion_status(body: &[u8]) -> GenResult<JobStatus>{
+    let value: Value = serde_json::from_slice(body)?;
+    let status = value.get("completion_status").or_else(|| value.get("completionStatus"));
+    match status.and_then(|status| status.as_str()) {
+        Some("finished") => Ok(JobStatus::Finished),
+        Some("failed") => Ok(JobStatus::Failed),
+        Some("canceled") => Ok(JobStatus::Canceled),
+        Some(status) => Err(From::from(format!("Unknown completion status {}", status))),
+        None => Err(From::from("The job update has no completion_status"))
+    }
+}
+
+
+/// Deserialize the body of a message from the `info-topic` exchange into \
+/// the event its routing key announces
+pub fn parse_info_event(routing_key: &str, body: &[u8]) -> GenResult<InfoEvent>{
+    let words: Vec<&str> = routing_key.split('.').collect();
+    if words.len() > 1 && words.last() == Some(&"delta") {
+        Ok(InfoEvent::JobPatch(serde_json::from_slice(body)?))
+    } else if words.len() > 1 && words.last() == Some(&"tasks") {
+        Ok(InfoEvent::TaskUpdates(serde_json::from_slice(body)?))
+    } else if words.contains(&"task") {
+        Ok(InfoEvent::TaskUpdate(serde_json::from_slice(body)?))
+    } else {
+        Ok(InfoEvent::JobUpdate(serde_json::from_slice(body)?))
+    }
+}
+
+
+/// Feed a log of info messages through `parse_info_event()`, so a dashboard \
+/// can rebuild its state offline the same way it follows the live updates. \
+/// Each line of the log is a json object holding the `routing_key` and the \
+/// `body` of one message. A body that is a json string is taken as the raw \
+/// message text, any other json is the message itself. Lines that aren't \
+/// such a record or don't parse are logged and skipped like a consumer \
+/// would. Returns the number of events handed to the callback:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::{InfoEvent, replay_info_log};
+/// let log = "{\"routing_key\": \"job.1.tasks\", \"body\": []}\n";
+/// let count = replay_info_log(log.as_bytes(), |event|{
+///     if let InfoEvent::TaskUpdates(tasks) = event { assert!(tasks.is_empty()); }
+/// }).expect("Couldn't read log");
+/// assert_eq!(count, 1);
+/// ```
+pub fn replay_info_log<R, F>(reader: R, mut callback: F) -> GenResult<usize> where R: BufRead, F: FnMut(InfoEvent){
+    let mut count = 0;
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() { continue; }
+        match parse_record(line.as_str()) {
+            Ok(event) => {
+                callback(event);
+                count += 1;
+            },
+            Err(err) => println!("Error: Skipping line {} of the info log: {}", number + 1, err)
+        }
+    }
+    Ok(count)
+}
+
+
+/// Parse a single line of an info log into its event
+fn parse_record(line: &str) -> GenResult<InfoEvent>{
+    let record: Value = serde_json::from_str(line)?;
+    let routing_key = match record.get("routing_key") {
+        Some(&Value::String(ref routing_key)) => routing_key.clone(),
+        _ => return Err(From::from("the record has no routing_key"))
+    };
+    let body = match record.get("body") {
+        Some(&Value::String(ref text)) => text.clone().into_bytes(),
+        Some(value) => serde_json::to_vec(value)?,
+        None => return Err(From::from("the record has no body"))
+    };
+    parse_info_event(routing_key.as_str(), &body)
+}
@@ -0,0 +1,58 @@
+//! Content hashes that let a consumer detect a body that was changed on its \
+//! way, e.g. by a buggy intermediary. The publisher puts the hex encoded \
+//! SHA-256 of the body into the `content-hash` header (see \
+//! `PublishOptions::content_hash`), the consumer computes it again and \
+//! compares:
+//! ```
+//! # extern crate bender_mq;
+//! # extern crate amqp;
+//! # use bender_mq::{content_hash, verify_content_hash, CONTENT_HASH_HEADER};
+//! # use amqp::protocol::basic::BasicProperties;
+//! # use amqp::{Table, TableEntry};
+//! # fn main(){
+//! assert_eq!(content_hash(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+//! let mut headers = Table::new();
+//! headers.insert(CONTENT_HASH_HEADER.to_string(), TableEntry::LongString(content_hash(b"abc")));
+//! let properties = BasicProperties{ headers: Some(headers), ..Default::default() };
+//! assert_eq!(verify_content_hash(&properties, b"abc"), Some(true));
+//! assert_eq!(verify_content_hash(&properties, b"abd"), Some(false));
+//! assert_eq!(verify_content_hash(&Default::default(), b"abc"), None);
+//! # }
+//! ```
+
+use amqp::{Table, TableEntry};
+use amqp::protocol::basic::BasicProperties;
+use sha2::{Digest, Sha256};
+
+
+/// The header that holds the hash of the message body
+pub const CONTENT_HASH_HEADER: &str = "content-hash";
+
+
+/// The SHA-256 of the body as lowercase hex
+pub fn content_hash(body: &[u8]) -> String{
+    Sha256::digest(body).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+
+/// Put the hash of the body into the `content-hash` header, keeping the \
+/// other headers
+pub fn attach_content_hash(properties: &mut BasicProperties, body: &[u8]){
+    let mut headers = properties.headers.take().unwrap_or_else(Table::new);
+    headers.insert(CONTENT_HASH_HEADER.to_string(), TableEntry::LongString(content_hash(body)));
+    properties.headers = Some(headers);
+}
+
+
+/// Compare the `content-hash` header with the hash of the body. None if the \
+/// message carries no hash, e.g. because it was published without \
+/// `PublishOptions::content_hash`
+pub fn verify_content_hash(properties: &BasicProperties, body: &[u8]) -> Option<bool>{
+    let headers = properties.headers.as_ref()?;
+    match headers.get(CONTENT_HASH_HEADER) {
+        Some(&TableEntry::LongString(ref hash)) => Some(hash.eq_ignore_ascii_case(content_hash(body).as_str())),
+        // A header of another type can't be a hash we published
+        Some(_) => Some(false),
+        None => None
+    }
+}
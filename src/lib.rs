@@ -29,27 +29,118 @@
 //! 
 //! ## API Methods
 //! Check out the examples at the [BenderMQ](trait.BenderMQ.html) trait definition
+//!
+//! ## Connections
+//! `BenderMQ` is implemented for the bare amqp `Channel` and for a
+//! [Connection](struct.Connection.html), which owns the session the channel was
+//! opened on and keeps it alive for as long as the channel is used
 
 
 extern crate bender_config;
 extern crate bender_job;
 extern crate amqp;
+extern crate amq_proto;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate url;
+#[cfg(feature = "protobuf")]
+extern crate prost;
+
+mod ack;
+mod backoff;
+mod batch;
+mod capabilities;
+mod confirms;
+mod connection;
+mod declared;
+mod delivery;
+mod error;
+mod flow;
+mod frames;
+mod hooks;
+mod info;
+mod integrity;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod options;
+mod outbox;
+mod patch;
+mod permissions;
+mod profile;
+mod properties;
+#[cfg(feature = "protobuf")]
+mod proto;
+mod queue;
+mod ratelimit;
+mod routing;
+mod session;
+mod stream;
+mod throughput;
+mod topology;
+#[cfg(feature = "tracing")]
+mod trace;
 
-use bender_job::task::Task;
-use bender_config::Config;
+use bender_job::task::{Task, TaskStatus};
 use bender_job::Job;
-use amqp::{Basic, Session, Table, protocol};
+use amqp::{AMQPError, Basic, Consumer, Session, Table, TableEntry, protocol};
+use amqp::protocol::basic::BasicProperties;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use flow::Intercepted;
+use hooks::Hooks;
 pub use amqp::Channel;
+pub use amq_proto::Frame;
+pub use delivery::Delivery;
+pub use ack::AckHandle;
+pub use backoff::{BackoffConfig, retry};
+pub use batch::{BatchedPublisher, explode_batch};
+pub use capabilities::Capabilities;
+pub use connection::{CloseOutcome, ConfirmReport, Connection, ConnectionBuilder, UndeclaredExchange};
+pub use error::BenderMqError;
+pub use info::{InfoEvent, JobStatus, parse_info_event, replay_info_log};
+pub use integrity::{CONTENT_HASH_HEADER, content_hash, verify_content_hash};
+pub use stream::{FileOffsetStore, OffsetStore, OffsetTracker, StreamOffset};
+pub use throughput::Throughput;
+pub use topology::{BindingSpec, ExchangeSpec, QueueSpec, Topology, intended_topology};
+pub use options::PublishOptions;
+pub use outbox::{Outbox, OutboxMessage, Reconnectable, ReconnectingPublisher};
+pub use patch::{apply_merge_patch, merge_diff};
+pub use profile::SerializationProfile;
+pub use properties::new_correlation_id;
+pub use queue::Overflow;
+pub use session::{options_from_url, url_from_env};
+pub use ratelimit::{RateLimitedPublisher, TokenBucket};
+pub use routing::{DefaultRoutingStrategy, RoutingKey, RoutingStrategy, WorkerCapabilities, capability_binding_keys, task_routing_key, topic_matches};
+#[cfg(feature = "protobuf")]
+pub use proto::decode_proto;
+#[cfg(feature = "tracing")]
+pub use trace::TraceContext;
+#[cfg(feature = "metrics")]
+pub use metrics::Histogram;
 
 
 type GenError = Box<std::error::Error>;
 type GenResult<T> = Result<T, GenError>;
 
 
+/// How many unacknowledged tasks the broker hands to `task_receiver()` at \
+/// once
+const TASK_RECEIVER_PREFETCH: u16 = 1;
+
+
 
 
-/// A trait for Channel to make it easier to post info
-pub trait BenderMQ{
+/// A trait for Channel to make it easier to post info. Implementors only \
+/// have to provide the constructors and access to their `Channel`, all other \
+/// methods build on top of that. The bookkeeping of declarations, consumers \
+/// and flow control happens behind the scenes, so the trait is implemented \
+/// by `Channel` and `Connection` only
+pub trait BenderMQ: Hooks{
     /// Open a AMPQ session and return a channel. The method can be used like this:
     /// ```
     /// extern crate bender_mq;
@@ -69,6 +160,96 @@ pub trait BenderMQ{
     /// ```
     fn open_default_channel() -> GenResult<Self> where Self: std::marker::Sized;
 
+    /// Open a AMPQ session and return a channel like `open_channel()`, but \
+    /// retry failed attempts with the delays of the given `BackoffConfig` \
+    /// instead of panicking. After `attempts` failures the last error is \
+    /// returned:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, BackoffConfig};
+    /// let backoff = BackoffConfig::default();
+    /// let channel = Channel::open_channel_with_backoff("amqp://localhost//", &backoff, 10).expect("Couldn't aquire connection.");
+    /// ```
+    fn open_channel_with_backoff<S>(url: S, backoff: &BackoffConfig, attempts: u32) -> GenResult<Self>
+    where S: Into<String>, Self: std::marker::Sized;
+
+    /// Open a AMPQ session and return a channel to the URL found in the \
+    /// environment, so no password has to be put into a config file. \
+    /// `BENDER_AMQP_URL` is used as is, otherwise the URL is put together \
+    /// from `BENDER_AMQP_HOST` and the optional `BENDER_AMQP_PORT`, \
+    /// `BENDER_AMQP_USER`, `BENDER_AMQP_PASS` and `BENDER_AMQP_VHOST`. If \
+    /// none of these are set the URL from the config is used:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// std::env::set_var("BENDER_AMQP_HOST", "rabbitmq.local");
+    /// std::env::set_var("BENDER_AMQP_USER", "bender");
+    /// std::env::set_var("BENDER_AMQP_PASS", "s3cr3t/!");
+    /// let channel = Channel::open_channel_from_env().expect("Couldn't aquire connection.");
+    /// ```
+    fn open_channel_from_env() -> GenResult<Self> where Self: std::marker::Sized{
+        match session::url_from_env() {
+            Some(url) => Self::open_channel(url),
+            None => Self::open_default_channel()
+        }
+    }
+
+    /// Open a AMPQ session like `open_channel()` and declare every exchange \
+    /// and queue with `declare_all()`, so the channel is ready to post and \
+    /// consume. If anything fails a `BenderMqError::SetupFailed` error \
+    /// names the step, either connecting or the declare of a single \
+    /// exchange or queue, and holds the error of that step:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let channel = match Channel::connect_and_setup("amqp://localhost//") {
+    ///     Ok(channel) => channel,
+    ///     Err(err) => panic!("Couldn't set up the broker: {}", err)
+    /// };
+    /// ```
+    fn connect_and_setup<S>(url: S) -> GenResult<Self> where S: Into<String>, Self: std::marker::Sized{
+        let mut mq = Self::open_channel(url).map_err(|err| BenderMqError::SetupFailed("connecting to the broker".to_string(), err))?;
+        mq.declare_all()?;
+        Ok(mq)
+    }
+
+    /// Returns the channel all other methods of this trait operate on
+    fn channel(&mut self) -> &mut Channel;
+
+    /// Declare every exchange and queue of `intended_topology()` with the \
+    /// declare methods, one after another. The `work-retry` queue is left \
+    /// out, as its delay has to be chosen with `create_retry_queue()`. The \
+    /// first failing declare stops and returns a \
+    /// `BenderMqError::SetupFailed` error naming it:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_all().expect("Declaration failed");
+    /// ```
+    fn declare_all(&mut self) -> GenResult<()> where Self: std::marker::Sized{
+        let steps: Vec<(&str, fn(&mut Self) -> GenResult<()>)> = vec![
+            ("exchange info-topic", Self::declare_topic_exchange),
+            ("exchange job", Self::declare_job_exchange),
+            ("exchange work", Self::declare_work_exchange),
+            ("exchange worker-topic", Self::declare_worker_exchange),
+            ("exchange dead-letter", Self::declare_dead_letter_exchange),
+            ("exchange work-priority", Self::declare_priority_work_exchange),
+            ("exchange work-partition", Self::declare_partition_work_exchange),
+            ("queue info", Self::create_info_queue),
+            ("queue job", Self::create_job_queue),
+            ("queue work", Self::create_work_queue),
+            ("queue worker", Self::create_worker_queue),
+            ("queue dead-letter", Self::create_dead_letter_queue),
+            ("queue work-priority", Self::create_priority_work_queue),
+            ("queue info-stream", Self::create_info_stream)
+        ];
+        for (name, declare) in steps {
+            declare(self).map_err(|err| BenderMqError::SetupFailed(format!("declaring {}", name), err))?;
+        }
+        Ok(())
+    }
+
     /// Declare a topic exchange named `info-topic`. Messages to this exchange \
     /// may be posted using the `post_to_info()` and the `post_job()` methods.
     /// ```
@@ -77,7 +258,15 @@ pub trait BenderMQ{
     /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
     /// channel.declare_topic_exchange().expect("Declaration of topic exchange failed");
     /// ```
-    fn declare_topic_exchange(&mut self) -> GenResult<()>;
+    fn declare_topic_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange info-topic") { return Ok(()); }
+        let exchange_name = "info-topic";
+        let exchange_type = "topic";
+        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange info-topic");
+        Ok(())
+    }
 
     /// Declare a direct exchange named `job`. Messages to this exchange \
     /// may be posted using the `post_job()` method.
@@ -87,17 +276,37 @@ pub trait BenderMQ{
     /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
     /// channel.declare_job_exchange().expect("Declaration of job exchange failed");
     /// ```
-    fn declare_job_exchange(&mut self) -> GenResult<()>;
+    fn declare_job_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange job") { return Ok(()); }
+        let exchange_name = "job";
+        let exchange_type = "direct";
+        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+        // posibble exchange types are: direct, fanout, topic, headers
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange job");
+        Ok(())
+    }
 
-    /// Declare a direct exchange named `work`. Messages to this exchange \
-    /// may be posted using the `post_task()` or `post_to_work()` methods.
+    /// Declare a topic exchange named `work`. Messages to this exchange \
+    /// may be posted using the `post_task()` or `post_to_work_routed()` \
+    /// methods. Tasks are routed as `<job_id>.<engine>.<frame>`, see \
+    /// `task_routing_key()`.
     /// ```
     /// # extern crate bender_mq;
     /// # use bender_mq::{Channel, BenderMQ};
     /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
     /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
     /// ```
-    fn declare_work_exchange(&mut self) -> GenResult<()>;
+    fn declare_work_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange work") { return Ok(()); }
+        let exchange_name = "work";
+        let exchange_type = "topic";
+        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+        // posibble exchange types are: direct, fanout, topic, headers
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange work");
+        Ok(())
+    }
 
     /// Declare a direct exchange named `worker-topic`. Messages to this exchange \
     /// may be posted using the `worker_post()` method.
@@ -107,262 +316,2388 @@ pub trait BenderMQ{
     /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
     /// channel.declare_worker_exchange().expect("Declaration of worker-topic exchange failed");
     /// ```
-    fn declare_worker_exchange(&mut self) -> GenResult<()>;
-
-    /// Declare a queue named `info`. This queue will be bound to the exchange \
-    /// named `info-topic`.
-    fn create_info_queue(&mut self) -> GenResult<()>;
-
-    /// Declare a queue named `job`. This queue will be bound to the exchange \
-    /// named `job`.
-    fn create_job_queue(&mut self) -> GenResult<()>;
-
-    /// Declare a queue named `work`. This queue will be bound to the exchange \
-    /// named `work`.
-    fn create_work_queue(&mut self) -> GenResult<()>;
-
-    /// Declare a queue named `worker`. This queue will be bound to the exchange \
-    /// named `worker-topic`.
-    fn create_worker_queue(&mut self) -> GenResult<()>;
-
-    /// Post a routed message to `info-topic` exchange with a routing key of your choice
-    fn post_to_info<S, U>(&mut self, routing_key: S, message: U) where S: Into<String>, U: Into<Vec<u8>>;
-    
-    /// Post a direct message to `job` exchange
-    fn post_to_job<U>(&mut self, message: U) where U: Into<Vec<u8>>;
-
-    /// Post a direct message to `work` exchange
-    fn post_to_work<U>(&mut self, message: U) where U: Into<Vec<u8>>;
-
-    /// Post a routed message to `worker-topic` exchange with a routing key of your choice
-    fn worker_post<S, U>(&mut self, routing_key: S, message: U) where S: Into<String>, U: Into<Vec<u8>>;
-
-    /// Serialize a job and post it to the the `job` exchange using the \
-    /// `post_to_job()` method. Get the serialized json back for debouncing
-    fn post_job(&mut self, job: &Job) -> GenResult<String>;
-
-    /// Serialize a job and post it to the the `topic-info` exchange using the \
-    /// `post_to_info()` method. Get the serialized json back for debouncing
-    fn post_job_info(&mut self, job: &Job) -> GenResult<String>;
-
-    /// Serialize a task and post it to the the `task` exchange using the \
-    /// `post_to_work()` method. Get the serialized json back for debouncing
-    fn post_task(&mut self, task: &Task) -> GenResult<String>;
-
-    /// Serialize a task and post it to the the `topic-info` exchange using the \
-    /// `post_to_info()` method. Get the serialized json back for debouncing
-    fn post_task_info<S>(&mut self, task: &Task, routing_key: S) -> GenResult<String> where S: Into<String>;
-}
-
-
-impl BenderMQ for Channel{
-    /// Open a AMPQ session and return a channel.
-    fn open_channel<S>(url: S) -> GenResult<Self> where S: Into<String>{
-        let url = url.into();
-        let mut session = Session::open_url(url.as_str()).unwrap_or_else(|_| panic!("Error while opening a connection to {}", url));
-        let channel = session.open_channel(1)?;
-        Ok(channel)
+    fn declare_worker_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange worker-topic") { return Ok(()); }
+        let exchange_name = "worker-topic";
+        let exchange_type = "topic";
+        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+        // posibble exchange types are: direct, fanout, topic, headers
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange worker-topic");
+        Ok(())
     }
 
-    /// Open a AMPQ session and return a channel to the default URK specified in\
-    /// the config.
-    fn open_default_channel() -> GenResult<Self>{
-        let config = Config::get();
-        let mut session = Session::open_url(config.rabbitmq.url.as_str()).unwrap_or_else(|_| panic!("Error while opening a connection to {}", config.rabbitmq.url));
-        let channel = session.open_channel(1)?;
-        Ok(channel)
+    /// Declare a topic exchange named `dead-letter`. Queues can name it as \
+    /// their `x-dead-letter-exchange`, and `nack_with_reason()` posts the \
+    /// messages it rejects there.
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_dead_letter_exchange().expect("Declaration of dead-letter exchange failed");
+    /// ```
+    fn declare_dead_letter_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange dead-letter") { return Ok(()); }
+        let exchange_name = "dead-letter";
+        let exchange_type = "topic";
+        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange dead-letter");
+        Ok(())
     }
 
-
-    /// Declare a topic exchange named `info-topic`. Messages to this exchange \
-    /// may be posted using the `post_to_info()` and the `post_job()` methods.
-    fn declare_topic_exchange(&mut self) -> GenResult<()>{
-        let exchange_name = "info-topic";
+    /// Declare a topic exchange named `work-priority`. Urgent tasks are \
+    /// posted there with `post_task_urgent()` and routed like on `work`, \
+    /// workers drain its queue before the normal one (see \
+    /// `consume_tasks_prioritized()`).
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_priority_work_exchange().expect("Declaration of work-priority exchange failed");
+    /// ```
+    fn declare_priority_work_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange work-priority") { return Ok(()); }
+        let exchange_name = "work-priority";
         let exchange_type = "topic";
         // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
-        self.exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange work-priority");
         Ok(())
     }
 
-    /// Create a info queue that is bound to the info-topic exchange
+    /// Declare a direct exchange named `work-partition`. Tasks are posted \
+    /// there with `post_task_partitioned()` and reach only the partition \
+    /// queue of their job (see `create_partition_work_queue()`), not the \
+    /// `work` queue.
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_partition_work_exchange().expect("Declaration of work-partition exchange failed");
+    /// ```
+    fn declare_partition_work_exchange(&mut self) -> GenResult<()>{
+        if self.is_declared("exchange work-partition") { return Ok(()); }
+        let exchange_name = "work-partition";
+        let exchange_type = "direct";
+        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+        self.channel().exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.record_declared("exchange work-partition");
+        Ok(())
+    }
+
+    /// Declare a queue named `info`. This queue will be bound to the exchange \
+    /// named `info-topic`.
     fn create_info_queue(&mut self) -> GenResult<()>{
+        if self.is_declared("queue info") { return Ok(()); }
         let queue_name = "info";
         let exchange_name = "info-topic";
         //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
-        self.queue_declare(queue_name, false, true, false, false, false, Table::new())?;
+        self.channel().queue_declare(queue_name, false, true, false, false, false, Table::new())?;
         // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
-        self.queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
-        Ok(())
-    }
-
-    /// Declare a direct exchange named `job`. Messages to this exchange \
-    /// may be posted using the `post_job()` method.
-    fn declare_job_exchange(&mut self) -> GenResult<()>{
-        let exchange_name = "job";
-        let exchange_type = "direct";
-        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
-        // posibble exchange types are: direct, fanout, topic, headers
-        self.exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue info");
         Ok(())
     }
 
-    /// Create a Job queue that is bound to the job exchange
+    /// Declare a queue named `job`. This queue will be bound to the exchange \
+    /// named `job`.
     fn create_job_queue(&mut self) -> GenResult<()>{
+        if self.is_declared("queue job") { return Ok(()); }
         let queue_name = "job";
         // let exchange_name = "job";
         //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
-        self.queue_declare(queue_name, false, true, false, false, false, Table::new())?;
+        self.channel().queue_declare(queue_name, false, true, false, false, false, Table::new())?;
         // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
-        // self.queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        // self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue job");
         Ok(())
     }
 
-    /// Declare a direct exchange named `work`. Messages to this exchange \
-    /// may be posted using the `post_work()` method.
-    fn declare_work_exchange(&mut self) -> GenResult<()>{
+    /// Declare a queue named `work`. This queue will be bound to the exchange \
+    /// named `work` and receives every task regardless of its engine. It is \
+    /// declared as a lazy queue if `lazy_work_queue()` says so.
+    fn create_work_queue(&mut self) -> GenResult<()>{
+        self.create_work_queue_with_ttl(None)
+    }
+
+    /// Declare the `work` queue like `create_work_queue()`, but let the \
+    /// broker drop tasks that waited longer than the given time (e.g. taken \
+    /// from your config), so stale tasks never run. The time can't be \
+    /// changed for an existing queue, trying to do so returns a \
+    /// `BenderMqError::QueueArgumentsMismatch` error:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// channel.create_work_queue_with_ttl(Some(Duration::from_secs(3600))).expect("Declaration of work queue failed");
+    /// ```
+    fn create_work_queue_with_ttl(&mut self, ttl: Option<Duration>) -> GenResult<()>{
+        self.create_work_queue_with_limit(ttl, None, Overflow::DropHead)
+    }
+
+    /// Declare the `work` queue like `create_work_queue_with_ttl()`, but hold \
+    /// at most `max_length` tasks, so an overwhelmed queue can't exhaust the \
+    /// memory of the broker. The `overflow` decides what happens to tasks \
+    /// posted to a full queue. With `Overflow::RejectPublish` they are \
+    /// refused, which publishers in confirm mode see as a nack (see \
+    /// `Connection::wait_for_confirm()`) and `Connection::post_task_confirmed()` \
+    /// as a `BenderMqError::QueueFull` error. Without a `max_length` the queue \
+    /// grows without limit. Like the ttl, the limit can't be changed for an \
+    /// existing queue:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, Overflow};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// channel.create_work_queue_with_limit(None, Some(100_000), Overflow::RejectPublish).expect("Declaration of work queue failed");
+    /// ```
+    fn create_work_queue_with_limit(&mut self, ttl: Option<Duration>, max_length: Option<u32>, overflow: Overflow) -> GenResult<()>{
+        if self.is_declared("queue work") { return Ok(()); }
+        let queue_name = "work";
         let exchange_name = "work";
-        let exchange_type = "direct";
-        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
-        // posibble exchange types are: direct, fanout, topic, headers
-        self.exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
+        let mut arguments = queue::ttl_arguments(ttl);
+        queue::length_arguments(&mut arguments, max_length, overflow);
+        queue::lazy_arguments(&mut arguments, self.lazy_work_queue());
+        queue::declare(self.channel(), queue_name, arguments)?;
+        if max_length.is_some() { self.record_overflow(queue_name, overflow); }
+        // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue work");
         Ok(())
     }
 
-    // Declare a topic exchange named `worker`. Messages to this exchange \
-    /// may be posted using the `worker_post()` method.
-    fn declare_worker_exchange(&mut self) -> GenResult<()>{
-        let exchange_name = "worker-topic";
-        let exchange_type = "topic";
-        // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
-        // posibble exchange types are: direct, fanout, topic, headers
-        self.exchange_declare(exchange_name, exchange_type, false, true, false, false, false, Table::new())?;
-        Ok(())
+    /// Whether the `work` queue is declared lazy (`x-queue-mode: lazy`), so \
+    /// the broker pages waiting tasks out to disk instead of holding them in \
+    /// memory. This bounds the memory of the broker when a large submission \
+    /// fills the queue with hundreds of thousands of tasks, at the price of \
+    /// a higher latency per task. False unless the implementor allows to \
+    /// change it. As with the ttl this can't be changed for an existing queue
+    fn lazy_work_queue(&self) -> bool{
+        false
     }
 
-    /// Create a Work queue that is bound to the work exchange
-    fn create_work_queue(&mut self) -> GenResult<()>{
-        let queue_name = "work";
-        // let exchange_name = "work";
+    /// Declare a queue named `work-priority`. This queue will be bound to the \
+    /// exchange named `work-priority` and receives every urgent task.
+    fn create_priority_work_queue(&mut self) -> GenResult<()>{
+        if self.is_declared("queue work-priority") { return Ok(()); }
+        let queue_name = "work-priority";
+        let exchange_name = "work-priority";
         //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
-        self.queue_declare(queue_name, false, true, false, false, false, Table::new())?;
+        self.channel().queue_declare(queue_name, false, true, false, false, false, Table::new())?;
         // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
-        // self.queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue work-priority");
         Ok(())
     }
 
-    /// Create a worker queue that is bound to the info-topic exchange
-    fn create_worker_queue(&mut self) -> GenResult<()>{
-        let queue_name = "worker";
-        let exchange_name = "worker-topic";
+    /// Declare a queue named `work.<engine>`. This queue will be bound to the \
+    /// exchange named `work` with the key `*.<engine>.#`, so it only receives \
+    /// the tasks for this render engine:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// channel.create_engine_work_queue("cycles").expect("Declaration of work.cycles queue failed");
+    /// ```
+    fn create_engine_work_queue<S>(&mut self, engine: S) -> GenResult<()> where S: Into<String>{
+        let engine = engine.into();
+        let queue_name = format!("work.{}", engine);
+        if self.is_declared(format!("queue {}", queue_name).as_str()) { return Ok(()); }
+        let exchange_name = "work";
+        let routing_key = routing::engine_binding_key(engine.as_str());
         //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
-        self.queue_declare(queue_name, false, true, false, false, false, Table::new())?;
+        self.channel().queue_declare(queue_name.as_str(), false, true, false, false, false, Table::new())?;
         // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
-        self.queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.channel().queue_bind(queue_name.as_str(), exchange_name, routing_key.as_str(), false, Table::new())?;
+        self.record_declared(format!("queue {}", queue_name).as_str());
         Ok(())
     }
 
-    /// Post a message to `info-topic` exchange with a routing key of your choice
-    fn post_to_info<S, U>(&mut self, routing_key: S, message: U) where S: Into<String>, U: Into<Vec<u8>>{
-        // let queue_name = "info";
-        let exchange = "info-topic";
-        let mandatory = true;
-        let immediate = false;
-        let routing_key = routing_key.into();
-        let routing_key = routing_key.as_str();
-        let properties = protocol::basic::BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
-        let message = message.into();
-        if let Err(err) = self.basic_publish(exchange, routing_key, mandatory, immediate, properties, message) { 
-            println!("Error: Couldn't publish message to info-topic exchange: {}", err) 
+    /// Bind a queue to the work exchange (see `work_exchange()`) with every \
+    /// key of `capability_binding_keys()`, so it receives exactly the tasks \
+    /// the worker can render:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, WorkerCapabilities};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// let queue = channel.create_private_worker_queue().expect("Declaration of private worker queue failed");
+    /// let capabilities = WorkerCapabilities{ engines: vec!["cycles".to_string()] };
+    /// channel.bind_worker_capabilities(queue, &capabilities).expect("Couldn't bind worker queue");
+    /// ```
+    fn bind_worker_capabilities<S>(&mut self, queue: S, capabilities: &WorkerCapabilities) -> GenResult<()> where S: Into<String>{
+        let queue = queue.into();
+        let exchange = self.work_exchange();
+        for routing_key in routing::capability_binding_keys(capabilities) {
+            // queue: S, exchange: S, routing_key: S, nowait: bool, arguments: Table
+            self.channel().queue_bind(queue.as_str(), exchange.as_str(), routing_key.as_str(), false, Table::new()).map_err(BenderMqError::from)?;
         }
+        Ok(())
     }
 
-
-    /// Post a direct message to `job` exchange
-    fn post_to_job<U>(&mut self, message: U) where U: Into<Vec<u8>>{
-        let mandatory = true;
-        let immediate = false;
-        let routing_key = "job".to_string();
-        let properties = protocol::basic::BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
-        let message = message.into();
-        if let Err(err) = self.basic_publish("", routing_key.as_str(), mandatory, immediate, properties, message) { 
-            println!("Error: Couldn't publish message to job exchange: {}", err) 
-        }
+    /// Declare a queue named `work.partition.<n>`. This queue will be bound \
+    /// to the exchange named `work-partition` with the key `partition.<n>`, \
+    /// so it only receives the tasks `post_task_partitioned()` put into this \
+    /// partition. A worker consuming it sees all tasks of its jobs, which \
+    /// keeps their assets in its cache:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_partition_work_exchange().expect("Declaration of work-partition exchange failed");
+    /// channel.create_partition_work_queue(3).expect("Declaration of work.partition.3 queue failed");
+    /// ```
+    fn create_partition_work_queue(&mut self, partition: u32) -> GenResult<()>{
+        let queue_name = format!("work.partition.{}", partition);
+        if self.is_declared(format!("queue {}", queue_name).as_str()) { return Ok(()); }
+        let exchange_name = "work-partition";
+        let routing_key = routing::partition_binding_key(partition);
+        //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
+        self.channel().queue_declare(queue_name.as_str(), false, true, false, false, false, Table::new())?;
+        // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+        self.channel().queue_bind(queue_name.as_str(), exchange_name, routing_key.as_str(), false, Table::new())?;
+        self.record_declared(format!("queue {}", queue_name).as_str());
+        Ok(())
     }
 
-    /// Post a direct message to `work` exchange
-    fn post_to_work<U>(&mut self, message: U) where U: Into<Vec<u8>>{
-        let mandatory = true;
-        let immediate = false;
-        let routing_key = "work".to_string();
-        let properties = protocol::basic::BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
-        let message = message.into();
-        if let Err(err) = self.basic_publish("", routing_key.as_str(), mandatory, immediate, properties, message) { 
-            println!("Error: Couldn't publish message to info-topic exchange: {}", err) 
-        }
+    /// Declare a queue named `worker`. This queue will be bound to the exchange \
+    /// named `worker-topic`.
+    fn create_worker_queue(&mut self) -> GenResult<()>{
+        if self.is_declared("queue worker") { return Ok(()); }
+        let queue_name = "worker";
+        let exchange_name = "worker-topic";
+        //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
+        self.channel().queue_declare(queue_name, false, true, false, false, false, Table::new())?;
+        // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue worker");
+        Ok(())
     }
 
-    // Post a message to `worker-topic` exchange with a routing key of your choice
-    fn worker_post<S, U>(&mut self, routing_key: S, message: U) where S: Into<String>, U: Into<Vec<u8>>{
-        // let queue_name = "worker";
-        let exchange = "worker-topic";
-        let mandatory = true;
-        let immediate = false;
-        let routing_key = routing_key.into();
-        let routing_key = routing_key.as_str();
-        let properties = protocol::basic::BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
-        let message = message.into();
-        if let Err(err) = self.basic_publish(exchange, routing_key, mandatory, immediate, properties, message) { 
-            println!("Error: Couldn't publish message to info-topic exchange: {}", err) 
-        }
+    /// Declare an exclusive queue named `worker.<hostname>.<pid>`, which is \
+    /// unique to this process, and bind it to the exchange named \
+    /// `worker-topic` with its name as routing key. Returns the name, so the \
+    /// worker can consume from the queue and an orchestrator can address \
+    /// this one worker with `worker_post()`. The queue is bound to \
+    /// `worker.all` as well, so it also receives the broadcasts of \
+    /// `broadcast_quorum()`. The queue is deleted once this connection closes:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_worker_exchange().expect("Declaration of worker-topic exchange failed");
+    /// let queue = channel.create_private_worker_queue().expect("Declaration of private worker queue failed");
+    /// assert!(queue.starts_with("worker."));
+    /// ```
+    fn create_private_worker_queue(&mut self) -> GenResult<String>{
+        let exchange_name = "worker-topic";
+        let queue_name = routing::private_worker_routing_key();
+        //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
+        self.channel().queue_declare(queue_name.as_str(), false, false, true, true, false, Table::new())?;
+        // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+        self.channel().queue_bind(queue_name.as_str(), exchange_name, queue_name.as_str(), false, Table::new())?;
+        self.channel().queue_bind(queue_name.as_str(), exchange_name, routing::WORKER_BROADCAST_KEY, false, Table::new())?;
+        Ok(queue_name)
     }
 
-
-    /// Serialize a job and post it to the the `job` exchange using the \
-    /// `post_to_job()` method. Get the serialized json back for debouncing
-    fn post_job(&mut self,job: &Job) -> GenResult<String>{
-        match job.serialize(){
-            Ok(json) => {
-                self.post_to_job(json.as_str());
-                Ok(json)
-            },
-            Err(err) => Err(err)
-        }
+    /// Declare a queue named `dead-letter`. This queue will be bound to the \
+    /// exchange named `dead-letter` and collects every rejected message for \
+    /// triage.
+    fn create_dead_letter_queue(&mut self) -> GenResult<()>{
+        if self.is_declared("queue dead-letter") { return Ok(()); }
+        let queue_name = "dead-letter";
+        let exchange_name = "dead-letter";
+        //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
+        self.channel().queue_declare(queue_name, false, true, false, false, false, Table::new())?;
+        // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue dead-letter");
+        Ok(())
     }
 
-    /// Serialize a job and post it to the the `topic-info` exchange using the \
-    /// `post_to_info()` method. Get the serialized json back for debouncing
-    fn post_job_info(&mut self, job: &Job) -> GenResult<String>{
-        match job.serialize(){
-            Ok(json) => {
-                self.post_to_info(job.id().as_str(), json.as_str());
-                Ok(json)
-            },
-            Err(err) => Err(err)
+    /// Declare a topic exchange and a queue both named `work-retry`. Tasks \
+    /// in the queue wait for the given delay and are then dead-lettered back \
+    /// into the `work` exchange with their original routing key. Failed \
+    /// tasks can be put there with `requeue_with_delay()`. The delay can't \
+    /// be changed once the queue exists.
+    fn create_retry_queue(&mut self, delay: Duration) -> GenResult<()>{
+        let exchange_name = "work-retry";
+        let queue_name = "work-retry";
+        if !self.is_declared("exchange work-retry") {
+            // exchange name, exchange type, passive, durable, auto_delete, internal, nowait, arguments
+            self.channel().exchange_declare(exchange_name, "topic", false, true, false, false, false, Table::new())?;
+            self.record_declared("exchange work-retry");
         }
+        if self.is_declared("queue work-retry") { return Ok(()); }
+        let mut arguments = queue::ttl_arguments(Some(delay));
+        arguments.insert("x-dead-letter-exchange".to_string(), TableEntry::LongString("work".to_string()));
+        queue::declare(self.channel(), queue_name, arguments)?;
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared("queue work-retry");
+        Ok(())
     }
 
-    /// Serialize a task and post it to the the `task` exchange using the \
-    /// `post_to_task()` method. Get the serialized json back for debouncing
-    fn post_task(&mut self, task: &Task) -> GenResult<String>{
-        match task.serialize(){
-            Ok(json) => {
-                self.post_to_work(json.as_str());
-                Ok(json)
-            },
-            Err(err) => Err(err)
-        }
+    /// Put a task that failed into the `work-retry` queue and acknowledge \
+    /// the delivery it came with, so it shows up in `work` again after the \
+    /// delay of `create_retry_queue()` instead of right away:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// # fn render(task: &bender_job::task::Task) -> Result<(), ()>{ Ok(()) }
+    /// # fn example(delivery_tag: u64, task: &bender_job::task::Task){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.create_retry_queue(Duration::from_secs(30)).expect("Declaration of retry queue failed");
+    /// if render(task).is_err() {
+    ///     channel.requeue_with_delay(delivery_tag, task).expect("Couldn't requeue task");
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn requeue_with_delay(&mut self, delivery_tag: u64, task: &Task) -> GenResult<()>{
+        let exchange = "work-retry";
+        let json = task.serialize()?;
+        let routing_key = RoutingKey::new(self.routing_strategy().task_key(task))?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        self.publish(exchange, routing_key.as_str(), properties, json.into_bytes())?;
+        self.channel().basic_ack(delivery_tag, false).map_err(BenderMqError::from)?;
+        Ok(())
     }
 
-
-    fn post_task_info<S>(&mut self, task: &Task, routing_key: S) -> GenResult<String> where S: Into<String>{
+    /// Declare an exclusive queue with a name chosen by the broker and \
+    /// return that name. The queue is deleted once this connection closes, \
+    /// which makes it a good `reply_to` address:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let queue = channel.declare_anonymous_queue().expect("Declaration of anonymous queue failed");
+    /// assert!(!queue.is_empty());
+    /// ```
+    fn declare_anonymous_queue(&mut self) -> GenResult<String>{
+        //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
+        let declare_ok = self.channel().queue_declare("", false, false, true, true, false, Table::new()).map_err(BenderMqError::from)?;
+        Ok(declare_ok.queue)
+    }
+
+    /// Bind a queue to an exchange with binding arguments. Headers exchanges \
+    /// route by these arguments instead of the routing key:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use amqp::{Table, TableEntry};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let queue = channel.declare_anonymous_queue().expect("Declaration of anonymous queue failed");
+    /// let mut arguments = Table::new();
+    /// arguments.insert("engine".to_string(), TableEntry::LongString("cycles".to_string()));
+    /// arguments.insert("x-match".to_string(), TableEntry::LongString("all".to_string()));
+    /// channel.bind_queue_with_args(queue.as_str(), "amq.headers", "", arguments).expect("Binding failed");
+    /// ```
+    fn bind_queue_with_args<S>(&mut self, queue: S, exchange: S, routing_key: S, arguments: Table) -> GenResult<()> where S: Into<String>{
+        // queue: S, exchange: S, routing_key: S, nowait: bool, arguments: Table
+        self.channel().queue_bind(queue.into(), exchange.into(), routing_key.into(), false, arguments).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Move a queue from one binding key to another. The new binding is \
+    /// added before the old one is removed, so there is no moment in which \
+    /// the queue receives nothing. Messages matching both keys in between \
+    /// are still delivered only once:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.rebind_queue("work.gpu", "work", "*.cycles.#", "*.eevee.#").expect("Rebinding failed");
+    /// ```
+    fn rebind_queue<S>(&mut self, queue: S, exchange: S, old_key: S, new_key: S) -> GenResult<()> where S: Into<String>{
+        let queue = queue.into();
+        let exchange = exchange.into();
+        // queue: S, exchange: S, routing_key: S, nowait: bool, arguments: Table
+        self.channel().queue_bind(queue.as_str(), exchange.as_str(), new_key.into().as_str(), false, Table::new()).map_err(BenderMqError::from)?;
+        let unbind = protocol::queue::Unbind{
+            ticket: 0,
+            queue,
+            exchange,
+            routing_key: old_key.into(),
+            arguments: Table::new()
+        };
+        let _: protocol::queue::UnbindOk = self.channel().rpc(&unbind, "queue.unbind-ok").map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Bind an exchange to another one, so every message the `source` \
+    /// exchange routes with a matching key is routed by the `destination` \
+    /// exchange as well. This is a RabbitMQ extension and both exchanges \
+    /// have to be declared already. To audit everything posted to `work` \
+    /// (with an `audit` exchange of your own) without publishing it twice:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// channel.bind_exchange("audit", "work", "#").expect("Couldn't bind audit to work");
+    /// ```
+    fn bind_exchange<S>(&mut self, destination: S, source: S, routing_key: S) -> GenResult<()> where S: Into<String>{
+        // destination: S, source: S, routing_key: S, arguments: Table
+        self.channel().exchange_bind(destination, source, routing_key, Table::new()).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Delete a queue, but only if no messages are waiting in it, so a \
+    /// teardown that runs too early can't drop queued tasks. Returns false \
+    /// if the queue still holds messages and was kept. The broker refuses \
+    /// such a delete by closing the channel, so open a new one before going \
+    /// on. A `Connection` still remembers a deleted queue as declared, see \
+    /// `Connection::clear_declare_cache()`:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// if !channel.delete_queue_if_empty("work.eevee").expect("Couldn't delete queue") {
+    ///     println!("Warning: work.eevee still holds tasks, keeping it");
+    /// }
+    /// ```
+    fn delete_queue_if_empty<S>(&mut self, queue: S) -> GenResult<bool> where S: Into<String>{
+        queue::delete_if_empty(self.channel(), queue.into().as_str())
+    }
+
+    /// Change the arguments of an existing durable queue (e.g. its \
+    /// `x-message-ttl`), which the broker refuses to do on a redeclaration. \
+    /// The messages are moved into a temporary queue named \
+    /// `<queue>.migrating`, the queue is deleted and declared with the new \
+    /// arguments and the messages are moved back. Deleting the queue also \
+    /// removes its bindings and cancels its consumers, so these have to be \
+    /// set up again afterwards. Messages routed to the queue while it is \
+    /// deleted are lost. \
+    /// The messages are moved with publisher confirms and acknowledged only \
+    /// once the broker confirmed them, and a queue is only deleted once it \
+    /// is empty. Otherwise it is kept and an error is returned. This puts \
+    /// the channel into confirm mode for good. A bare `Channel` counts the \
+    /// confirms from 1, so migrate on a fresh one (or on a `Connection`, which \
+    /// keeps track of its confirms):
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use amqp::{Table, TableEntry};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let mut arguments = Table::new();
+    /// arguments.insert("x-max-priority".to_string(), TableEntry::ShortShortUint(10));
+    /// channel.redeclare_queue_migrating("work", arguments).expect("Couldn't migrate the work queue");
+    /// channel.bind_queue_with_args("work", "work", "#", Table::new()).expect("Binding failed");
+    /// ```
+    fn redeclare_queue_migrating<S>(&mut self, queue: S, arguments: Table) -> GenResult<()> where S: Into<String>{
+        let queue = queue.into();
+        let temporary = format!("{}.migrating", queue);
+        queue::declare(self.channel(), temporary.as_str(), Table::new())?;
+        queue::move_messages(self, queue.as_str(), temporary.as_str())?;
+        queue::delete_moved(self.channel(), queue.as_str())?;
+        queue::declare(self.channel(), queue.as_str(), arguments)?;
+        queue::move_messages(self, temporary.as_str(), queue.as_str())?;
+        queue::delete_moved(self.channel(), temporary.as_str())?;
+        Ok(())
+    }
+
+    /// Declare one of the exchanges above by its name. Returns a \
+    /// `BenderMqError::ExchangeNotDeclared` error for any other name
+    fn declare_exchange(&mut self, exchange: &str) -> GenResult<()>{
+        match exchange {
+            "info-topic" => self.declare_topic_exchange(),
+            "job" => self.declare_job_exchange(),
+            "work" => self.declare_work_exchange(),
+            "worker-topic" => self.declare_worker_exchange(),
+            "dead-letter" => self.declare_dead_letter_exchange(),
+            "work-priority" => self.declare_priority_work_exchange(),
+            "work-partition" => self.declare_partition_work_exchange(),
+            _ => Err(Box::new(BenderMqError::ExchangeNotDeclared(exchange.to_string())))
+        }
+    }
+
+    /// Returns true if the broker paused publishing on this channel via \
+    /// `channel.flow`. While this is the case the post methods return a \
+    /// `BenderMqError::FlowBlocked` error instead of publishing, so a \
+    /// producer can back off and retry later. The state is learned from the \
+    /// frames read from the channel. A bare `Channel` doesn't keep it and \
+    /// always returns false, a `Connection` does:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, RoutingKey};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// if !connection.is_flow_blocked(){
+    ///     let routing_key = RoutingKey::new("status").expect("Invalid routing key");
+    ///     connection.post_to_info(routing_key, "hello").expect("Couldn't post to info");
+    /// }
+    /// ```
+    fn is_flow_blocked(&self) -> bool{
+        false
+    }
+
+    /// Block until the next frame that isn't a delivery arrives on this \
+    /// channel. `channel.flow` frames are answered and recorded on the way, \
+    /// so `is_flow_blocked()` stays up to date while reading.
+    fn next_frame(&mut self) -> GenResult<Frame>{
+        loop {
+            let frame = self.channel().read().map_err(BenderMqError::from)?;
+            match flow::intercept(self.channel(), frame)? {
+                Intercepted::Frame(frame) => return Ok(frame),
+                Intercepted::Flow(blocked) => self.record_flow(blocked)
+            }
+        }
+    }
+
+    /// The flags all post methods publish with. Messages are mandatory, not \
+    /// immediate and without a content hash unless the implementor allows \
+    /// to change this
+    fn publish_options(&self) -> PublishOptions{
+        PublishOptions::default()
+    }
+
+    /// Publish a message to an exchange with the `publish_options()`. All \
+    /// post methods go through this method
+    fn publish(&mut self, exchange: &str, routing_key: &str, properties: BasicProperties, message: Vec<u8>) -> GenResult<()>{
+        let options = self.publish_options();
+        self.publish_with(exchange, routing_key, properties, message, options)
+    }
+
+    /// Publish a message to an exchange with the given flags. This refuses \
+    /// to publish while the broker applies flow control. To publish a \
+    /// message that may be dropped silently if no queue is bound:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, PublishOptions};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let options = PublishOptions{ mandatory: false, ..Default::default() };
+    /// channel.publish_with("info-topic", "status", Default::default(), b"hello".to_vec(), options).expect("Couldn't publish");
+    /// ```
+    fn publish_with(&mut self, exchange: &str, routing_key: &str, mut properties: BasicProperties, message: Vec<u8>, options: PublishOptions) -> GenResult<()>{
+        if self.is_flow_blocked() { return Err(Box::new(BenderMqError::FlowBlocked)); }
+        if options.content_hash { integrity::attach_content_hash(&mut properties, &message); }
+        self.channel().basic_publish(exchange, routing_key, options.mandatory, options.immediate, properties, message).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Publish a message with exactly the given properties (e.g. `_type`, \
+    /// `reply_to`, `user_id`, `cluster_id` or `headers`) and the \
+    /// `publish_options()`. Unlike `publish()` no default properties are \
+    /// merged in, so this is the escape hatch for properties the post \
+    /// methods don't cover. Flow control is respected like everywhere else:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use amqp::protocol::basic::BasicProperties;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let properties = BasicProperties{ _type: Some("render.request".to_string()), reply_to: Some("replies".to_string()), ..Default::default() };
+    /// channel.publish_raw("job", "job", properties, "{}").expect("Couldn't publish");
+    /// ```
+    fn publish_raw<S, U>(&mut self, exchange: S, routing_key: S, mut properties: BasicProperties, body: U) -> GenResult<()> where S: Into<String>, U: Into<Vec<u8>>{
+        if self.is_flow_blocked() { return Err(Box::new(BenderMqError::FlowBlocked)); }
+        let options = self.publish_options();
+        let exchange = exchange.into();
+        let routing_key = routing_key.into();
+        let body = body.into();
+        if options.content_hash { integrity::attach_content_hash(&mut properties, &body); }
+        self.channel().basic_publish(exchange.as_str(), routing_key.as_str(), options.mandatory, options.immediate, properties, body).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Post a routed message to `info-topic` exchange with a routing key of \
+    /// your choice (see `RoutingKey`). The message is transient
+    fn post_to_info<U>(&mut self, routing_key: RoutingKey, message: U) -> GenResult<()> where U: Into<Vec<u8>>{
+        self.post_to_info_with_mode(routing_key, message, false)
+    }
+
+    /// Post a routed message to `info-topic` exchange like `post_to_info()`. \
+    /// If `persistent` is set the message is written to disk and survives a \
+    /// broker restart (in a durable queue). Use it for the few updates that \
+    /// matter, like a final job status, and keep the bulk transient:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, RoutingKey};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let routing_key = RoutingKey::new("job.123.status").expect("Invalid routing key");
+    /// channel.post_to_info_with_mode(routing_key, "finished", true).expect("Couldn't post to info");
+    /// ```
+    fn post_to_info_with_mode<U>(&mut self, routing_key: RoutingKey, message: U, persistent: bool) -> GenResult<()> where U: Into<Vec<u8>>{
+        // let queue_name = "info";
+        let exchange = "info-topic";
+        let routing_key = routing_key.as_str();
+        let properties = BasicProperties{ content_type: Some("text".to_string()), delivery_mode: properties::delivery_mode(persistent), ..Default::default()};
+        let message = message.into();
+        self.publish(exchange, routing_key, properties, message)
+    }
+    
+    /// Post a direct message to `job` exchange
+    fn post_to_job<U>(&mut self, message: U) -> GenResult<()> where U: Into<Vec<u8>>{
+        let routing_key = "job".to_string();
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        let message = message.into();
+        self.publish("", routing_key.as_str(), properties, message)
+    }
+
+    /// Post a direct message to `work` exchange
+    fn post_to_work<U>(&mut self, message: U) -> GenResult<()> where U: Into<Vec<u8>>{
+        let routing_key = "work".to_string();
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        let message = message.into();
+        self.publish("", routing_key.as_str(), properties, message)
+    }
+
+    /// The exchange `post_task()` and `post_to_work_routed()` post to. This \
+    /// is `work` unless the implementor allows to change it
+    fn work_exchange(&self) -> String{
+        "work".to_string()
+    }
+
+    /// The routing keys `post_job_info()` and `post_task()` post with. This \
+    /// is the `DefaultRoutingStrategy` unless the implementor allows to \
+    /// change it
+    fn routing_strategy(&self) -> &RoutingStrategy{
+        &DefaultRoutingStrategy
+    }
+
+    /// The profile the post methods apply to serialized jobs and tasks \
+    /// before publishing them, e.g. to rename their fields to camelCase for \
+    /// a consumer that isn't written in rust. This is \
+    /// `SerializationProfile::AsIs` unless the implementor allows to change \
+    /// it. The consumers of this crate (e.g. `parse_info_event()` and the \
+    /// workers deserializing tasks) expect the fields as they are
+    fn serialization_profile(&self) -> SerializationProfile{
+        SerializationProfile::AsIs
+    }
+
+    /// Post a routed message to the `work` exchange (or the one returned by \
+    /// `work_exchange()`) with a routing key of your choice (see `RoutingKey`)
+    fn post_to_work_routed<U>(&mut self, routing_key: RoutingKey, message: U) -> GenResult<()> where U: Into<Vec<u8>>{
+        let exchange = self.work_exchange();
+        let routing_key = routing_key.as_str();
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        let message = message.into();
+        self.publish(exchange.as_str(), routing_key, properties, message)
+    }
+
+    /// Post a message that is already encoded (e.g. compressed with zstd) to \
+    /// any exchange. The body is published as it is, tagged with the given \
+    /// `content_encoding` so consumers know how to decode it. The json \
+    /// helpers of this crate (e.g. `consume_json()`, `Delivery::json()`) \
+    /// refuse such bodies with a `BenderMqError::UnsupportedEncoding` error:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(channel: &mut Channel, compressed: Vec<u8>){
+    /// channel.post_encoded("info-topic", "job.status", "zstd", compressed).expect("Couldn't post message");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_encoded<S, U>(&mut self, exchange: &str, routing_key: S, content_encoding: &str, message: U) -> GenResult<()> where S: Into<String>, U: Into<Vec<u8>>{
+        let routing_key = routing_key.into();
+        let properties = BasicProperties{
+            content_type: Some("text".to_string()),
+            content_encoding: Some(content_encoding.to_string()),
+            ..Default::default()
+        };
+        self.publish(exchange, routing_key.as_str(), properties, message.into())
+    }
+
+    /// Post a message to any exchange with the AMQP `type` property set to \
+    /// `message_type` (e.g. `render`, `cancel` or `pause`). This way a single \
+    /// queue can carry several kinds of commands and a consumer dispatches on \
+    /// `Delivery::message_type()` without peeking into the body:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(channel: &mut Channel, job_id: &str){
+    /// channel.post_typed("work", "job.command", "cancel", job_id).expect("Couldn't post message");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_typed<S, U>(&mut self, exchange: &str, routing_key: S, message_type: &str, message: U) -> GenResult<()> where S: Into<String>, U: Into<Vec<u8>>{
         let routing_key = routing_key.into();
-        match task.serialize(){
+        let properties = BasicProperties{
+            content_type: Some("text".to_string()),
+            _type: Some(message_type.to_string()),
+            ..Default::default()
+        };
+        self.publish(exchange, routing_key.as_str(), properties, message.into())
+    }
+
+    /// Post a routed message to `worker-topic` exchange with a routing key of \
+    /// your choice (see `RoutingKey`)
+    fn worker_post<U>(&mut self, routing_key: RoutingKey, message: U) -> GenResult<()> where U: Into<Vec<u8>>{
+        // let queue_name = "worker";
+        let exchange = "worker-topic";
+        let routing_key = routing_key.as_str();
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        let message = message.into();
+        self.publish(exchange, routing_key, properties, message)
+    }
+
+    /// Announce that a worker is alive. A small json message with the id \
+    /// and the current unix time, e.g. `{"worker_id":"node-1","timestamp":1700000000}`, \
+    /// is posted to the `worker-topic` exchange with the routing key \
+    /// `worker.<id>.heartbeat`. Dots in the id are replaced with underscores, \
+    /// so an orchestrator binds to `worker.*.heartbeat` and flags every \
+    /// worker it didn't hear from for a while. Heartbeats expire after 30 \
+    /// seconds, so a queue nobody reads doesn't fill up with them:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_worker_exchange().expect("Declaration of worker-topic exchange failed");
+    /// loop {
+    ///     channel.post_worker_heartbeat("render-node-1").expect("Couldn't post heartbeat");
+    ///     std::thread::sleep(Duration::from_secs(10));
+    /// }
+    /// ```
+    fn post_worker_heartbeat<S>(&mut self, worker_id: S) -> GenResult<()> where S: Into<String>{
+        let worker_id = worker_id.into();
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+        let routing_key = RoutingKey::new(routing::heartbeat_routing_key(worker_id.as_str()))?;
+        let mut message = serde_json::Map::new();
+        message.insert("worker_id".to_string(), serde_json::Value::from(worker_id));
+        message.insert("timestamp".to_string(), serde_json::Value::from(timestamp));
+        let message = serde_json::to_vec(&message)?;
+        let properties = BasicProperties{
+            content_type: Some("application/json".to_string()),
+            // in milliseconds
+            expiration: Some("30000".to_string()),
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
+        self.publish("worker-topic", routing_key.as_str(), properties, message)
+    }
+
+    /// Serialize a job and post it to the the `job` exchange using the \
+    /// `post_to_job()` method. Get the serialized json back for debouncing
+    fn post_job(&mut self, job: &Job) -> GenResult<String>{
+        match job.serialize().and_then(|json| self.serialization_profile().apply(json)){
+            Ok(json) => {
+                self.post_to_job(json.as_str())?;
+                Ok(json)
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Serialize a job and post it with the routing key `job` to each of the \
+    /// given exchanges inside a transaction, so either every exchange gets \
+    /// it or none does. The empty exchange name is the default exchange, \
+    /// which delivers straight to the `job` queue like `post_job()`. If a \
+    /// publish or the commit fails the transaction is rolled back and the \
+    /// error returned. The channel stays in transaction mode afterwards, \
+    /// which can't be combined with confirm mode. Get the serialized json \
+    /// back for debouncing:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(job: &bender_job::Job){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let exchanges = vec!["".to_string(), "audit".to_string()];
+    /// channel.post_job_multi(job, &exchanges).expect("Couldn't post job");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_job_multi(&mut self, job: &Job, exchanges: &[String]) -> GenResult<String>{
+        let json = self.serialization_profile().apply(job.serialize()?)?;
+        let _: protocol::tx::SelectOk = self.channel().rpc(&protocol::tx::Select, "tx.select-ok").map_err(BenderMqError::from)?;
+        let mut published: GenResult<()> = Ok(());
+        for exchange in exchanges {
+            let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+            published = self.publish(exchange.as_str(), "job", properties, json.clone().into_bytes());
+            if published.is_err() { break; }
+        }
+        if published.is_ok() {
+            let committed: Result<protocol::tx::CommitOk, _> = self.channel().rpc(&protocol::tx::Commit, "tx.commit-ok");
+            published = committed.map(|_| ()).map_err(|err| From::from(BenderMqError::from(err)));
+        }
+        match published {
+            Ok(()) => Ok(json),
+            Err(err) => {
+                let rolled_back: Result<protocol::tx::RollbackOk, _> = self.channel().rpc(&protocol::tx::Rollback, "tx.rollback-ok");
+                if let Err(rollback_err) = rolled_back {
+                    println!("Error: Couldn't roll back the transaction: {}", rollback_err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Serialize a job and post it to the the `topic-info` exchange using the \
+    /// `post_to_info()` method, routed by the job id or the key of the \
+    /// `routing_strategy()`. Get the serialized json back for debouncing
+    fn post_job_info(&mut self, job: &Job) -> GenResult<String>{
+        self.post_job_info_with_mode(job, false)
+    }
+
+    /// Serialize a job and post it to the the `topic-info` exchange using the \
+    /// `post_to_info_with_mode()` method, persistent if `persistent` is set. \
+    /// Get the serialized json back for debouncing
+    fn post_job_info_with_mode(&mut self, job: &Job, persistent: bool) -> GenResult<String>{
+        match job.serialize().and_then(|json| self.serialization_profile().apply(json)){
+            Ok(json) => {
+                let routing_key = RoutingKey::new(self.routing_strategy().job_info_key(job))?;
+                self.post_to_info_with_mode(routing_key, json.as_str(), persistent)?;
+                Ok(json)
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Post only what changed between two states of a job, e.g. for frequent \
+    /// progress updates. The json merge patch of `merge_diff()` is posted to \
+    /// the `info-topic` exchange with the info key of the job (see \
+    /// `routing_strategy()`) followed by `.delta`, which `parse_info_event()` \
+    /// reads as an `InfoEvent::JobPatch`. Consumers apply it to their cached \
+    /// job with `apply_merge_patch()`, so they need a full update (see \
+    /// `post_job_info()`) to start from. Returns the posted patch, or None \
+    /// without posting if nothing changed:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(channel: &mut Channel, previous: &bender_job::Job, current: &bender_job::Job){
+    /// if let Some(patch) = channel.post_job_delta(previous, current).expect("Couldn't post delta") {
+    ///     println!("Posted {} bytes instead of the whole job", patch.len());
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_job_delta(&mut self, previous: &Job, current: &Job) -> GenResult<Option<String>>{
+        let before: serde_json::Value = serde_json::from_str(previous.serialize()?.as_str())?;
+        let after: serde_json::Value = serde_json::from_str(current.serialize()?.as_str())?;
+        let patch = match patch::merge_diff(&before, &after) {
+            Some(patch) => self.serialization_profile().apply(patch.to_string())?,
+            None => return Ok(None)
+        };
+        let routing_key = RoutingKey::new(format!("{}.delta", self.routing_strategy().job_info_key(current)))?;
+        let properties = BasicProperties{ content_type: Some("application/merge-patch+json".to_string()), ..Default::default()};
+        self.publish("info-topic", routing_key.as_str(), properties, patch.clone().into_bytes())?;
+        Ok(Some(patch))
+    }
+
+    /// Post the final update of a job once it ended. The serialized job gets \
+    /// the status embedded as `completion_status` and is posted to the `job` \
+    /// queue like `post_job()` and to the `info-topic` exchange with the \
+    /// routing key `job.<id>.complete`, so a dashboard can bind to \
+    /// `job.*.complete` to learn about every finished job. The update is \
+    /// persistent, so it survives a broker restart. Get the posted json back:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ, JobStatus};
+    /// # fn example(job: &bender_job::Job){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.post_job_complete(job, JobStatus::Finished).expect("Couldn't post completion");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_job_complete(&mut self, job: &Job, status: JobStatus) -> GenResult<String>{
+        let mut value: serde_json::Value = serde_json::from_str(job.serialize()?.as_str())?;
+        match value.as_object_mut() {
+            Some(object) => { object.insert("completion_status".to_string(), serde_json::Value::from(status.as_str())); },
+            None => return Err(From::from(format!("The job {} didn't serialize to a json object", job.id())))
+        }
+        let json = self.serialization_profile().apply(value.to_string())?;
+        let routing_key = RoutingKey::new(format!("job.{}.complete", job.id()))?;
+        for &(exchange, routing_key) in &[("", "job"), ("info-topic", routing_key.as_str())] {
+            let properties = BasicProperties{ content_type: Some("text".to_string()), delivery_mode: properties::delivery_mode(true), ..Default::default()};
+            self.publish(exchange, routing_key, properties, json.clone().into_bytes())?;
+        }
+        Ok(json)
+    }
+
+    /// Post a job like `post_job()` and block until its completion (see \
+    /// `post_job_complete()`) arrives on the `info-topic` exchange, so a \
+    /// caller can treat the submission like a request with a response. A \
+    /// temporary queue is bound to `job.<id>.complete` before posting, so \
+    /// even a job that completes right away isn't missed. Returns a \
+    /// `BenderMqError::Timeout` error if the job didn't complete in time. \
+    /// The deadline is kept by the broker: a marker waits out the timeout in \
+    /// a second temporary queue and is then dead-lettered into the first \
+    /// one, so the wait ends even if nothing else arrives. Both queues are \
+    /// deleted before this returns:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ, JobStatus};
+    /// # use std::time::Duration;
+    /// # fn example(job: &bender_job::Job){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// match channel.submit_and_await(job, Duration::from_secs(3600)).expect("Job didn't complete") {
+    ///     JobStatus::Finished => println!("Done"),
+    ///     status => println!("Job ended as {}", status.as_str())
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn submit_and_await(&mut self, job: &Job, timeout: Duration) -> GenResult<JobStatus>{
+        // Not auto-delete, cancelling the consumer would delete it before it is deleted here
+        //queue: &str, passive: bool, durable: bool, exclusive: bool, auto_delete: bool, nowait: bool, arguments: Table
+        let queue = self.channel().queue_declare("", false, false, true, false, false, Table::new()).map_err(BenderMqError::from)?.queue;
+        let mut arguments = queue::ttl_arguments(Some(timeout));
+        arguments.insert("x-dead-letter-exchange".to_string(), TableEntry::LongString("".to_string()));
+        arguments.insert("x-dead-letter-routing-key".to_string(), TableEntry::LongString(queue.clone()));
+        let timer = match self.channel().queue_declare("", false, false, true, true, false, arguments) {
+            Ok(declare_ok) => declare_ok.queue,
+            Err(err) => {
+                let _ = queue::delete(self.channel(), queue.as_str());
+                return Err(Box::new(BenderMqError::from(err)));
+            }
+        };
+        let routing_key = format!("job.{}.complete", job.id());
+        let completion = (|| -> GenResult<Option<Vec<u8>>> {
+            // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+            self.channel().queue_bind(queue.as_str(), "info-topic", routing_key.as_str(), false, Table::new()).map_err(BenderMqError::from)?;
+            self.post_job(job)?;
+            self.publish("", timer.as_str(), BasicProperties::default(), Vec::new())?;
+            let (sender, receiver) = mpsc::channel();
+            let mut cancelled = false;
+            let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, _properties: BasicProperties, body: Vec<u8>|{
+                if cancelled { return; }
+                cancelled = true;
+                // The marker keeps the name of the queue as its routing key
+                let _ = sender.send((deliver.routing_key, body));
+                let cancel = protocol::basic::Cancel{ consumer_tag: deliver.consumer_tag.clone(), nowait: false };
+                if let Err(err) = channel.send_method_frame(&cancel) {
+                    println!("Error: Couldn't cancel consumer {}: {}", deliver.consumer_tag, err)
+                }
+            };
+            // callback, queue, consumer_tag, no_local, no_ack, exclusive, nowait, arguments
+            self.channel().basic_consume(consumer, queue.clone(), "".to_string(), false, true, true, false, Table::new()).map_err(BenderMqError::from)?;
+            loop {
+                // Deliveries are dispatched to the consumer while reading
+                let frame = self.next_frame()?;
+                match frames::method_name(&frame) {
+                    Some("basic.cancel-ok") => break,
+                    Some("basic.cancel") => {
+                        let cancel: protocol::basic::Cancel = frames::decode(&frame)?;
+                        return Err(Box::new(BenderMqError::ConsumerCancelled(cancel.consumer_tag)));
+                    },
+                    _ => self.record_frame(&frame)
+                }
+            }
+            Ok(match receiver.try_recv() {
+                Ok((ref key, ref body)) if *key == routing_key => Some(body.clone()),
+                _ => None
+            })
+        })();
+        let deleted = queue::delete(self.channel(), timer.as_str()).and_then(|_| queue::delete(self.channel(), queue.as_str()));
+        let completion = completion?;
+        deleted?;
+        match completion {
+            Some(body) => info::completion_status(&body),
+            None => Err(Box::new(BenderMqError::Timeout))
+        }
+    }
+
+    /// Serialize a task and post it to the the `work` exchange using the \
+    /// `post_to_work_routed()` method, routed by its job id, engine and frame \
+    /// (see `task_routing_key()`) or the key of the `routing_strategy()`. Get \
+    /// the serialized json back for debouncing
+    fn post_task(&mut self, task: &Task) -> GenResult<String>{
+        match task.serialize().and_then(|json| self.serialization_profile().apply(json)){
+            Ok(json) => {
+                let routing_key = RoutingKey::new(self.routing_strategy().task_key(task))?;
+                self.post_to_work_routed(routing_key, json.as_str())?;
+                Ok(json)
+            },
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Post a task like `post_task()`, but mandatory and wait for the \
+    /// broker to tell whether it was routed to at least one queue. Returns \
+    /// false if the broker returned the task as unroutable (e.g. because no \
+    /// queue is bound for its engine), true if it landed in a queue. To get \
+    /// a synchronous answer a harmless request follows the task: the broker \
+    /// answers in order, so a `basic.return` always arrives before its reply. \
+    /// Returns of earlier publishes to another exchange or routing key are \
+    /// passed on like any other frame read on the way. \
+    /// This costs a round trip per task, use it where the answer matters:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(task: &bender_job::task::Task){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// if !channel.post_task_routed_check(task).expect("Couldn't post task") {
+    ///     println!("Warning: No queue takes tasks like {:?}", task);
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_task_routed_check(&mut self, task: &Task) -> GenResult<bool>{
+        let json = self.serialization_profile().apply(task.serialize()?)?;
+        let exchange = self.work_exchange();
+        let routing_key = RoutingKey::new(self.routing_strategy().task_key(task))?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        let options = PublishOptions{ mandatory: true, ..self.publish_options() };
+        self.publish_with(exchange.as_str(), routing_key.as_str(), properties, json.into_bytes(), options)?;
+        let declare = protocol::exchange::Declare{
+            ticket: 0,
+            exchange: "amq.direct".to_string(),
+            _type: "direct".to_string(),
+            passive: true,
+            durable: true,
+            auto_delete: false,
+            internal: false,
+            nowait: false,
+            arguments: Table::new()
+        };
+        self.channel().send_method_frame(&declare).map_err(BenderMqError::from)?;
+        let mut routed = true;
+        loop {
+            let frame = self.next_frame()?;
+            match frames::method_name(&frame) {
+                Some("basic.return") => {
+                    // A return of an earlier mandatory publish may still be on its way
+                    let returned: protocol::basic::Return = frames::decode(&frame)?;
+                    if returned.exchange == exchange && returned.routing_key == routing_key.as_str() {
+                        routed = false;
+                    } else {
+                        self.record_frame(&frame);
+                    }
+                },
+                Some("exchange.declare-ok") => return Ok(routed),
+                Some("channel.close") => {
+                    let close: protocol::channel::Close = frames::decode(&frame)?;
+                    let _ = self.channel().send_method_frame(&protocol::channel::CloseOk);
+                    return Err(From::from(format!("The broker closed the channel after publishing to {}: {}", exchange, close.reply_text)));
+                },
+                // The header and body of a returned task and e.g. confirms
+                _ => self.record_frame(&frame)
+            }
+        }
+    }
+
+    /// Serialize a task and post it to the `work-partition` exchange with \
+    /// the routing key `partition.<n>`. The partition is picked by hashing \
+    /// the job id modulo `partitions`, so every task of a job goes to the \
+    /// same `work.partition.<n>` queue (see `create_partition_work_queue()`) \
+    /// and to no other queue. Get the serialized json back for debouncing:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(task: &bender_job::task::Task){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.post_task_partitioned(task, 8).expect("Couldn't post task");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_task_partitioned(&mut self, task: &Task, partitions: u32) -> GenResult<String>{
+        let json = task.serialize()?;
+        let routing_key = RoutingKey::new(routing::partition_routing_key(json.as_str(), partitions))?;
+        let json = self.serialization_profile().apply(json)?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        self.publish("work-partition", routing_key.as_str(), properties, json.clone().into_bytes())?;
+        Ok(json)
+    }
+
+    /// Serialize a task and post it to the `work-priority` exchange, routed \
+    /// like `post_task()` by the `routing_strategy()`. Workers using `consume_tasks_prioritized()` take \
+    /// it before any task waiting in the `work` queue, however deep that \
+    /// is. Get the serialized json back for debouncing
+    fn post_task_urgent(&mut self, task: &Task) -> GenResult<String>{
+        let routing_key = RoutingKey::new(self.routing_strategy().task_key(task))?;
+        let json = self.serialization_profile().apply(task.serialize()?)?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        self.publish("work-priority", routing_key.as_str(), properties, json.clone().into_bytes())?;
+        Ok(json)
+    }
+
+    /// Serialize a task and post it to the the `topic-info` exchange using the \
+    /// `post_to_info()` method. Get the serialized json back for debouncing
+    fn post_task_info(&mut self, task: &Task, routing_key: RoutingKey) -> GenResult<String>{
+        match task.serialize().and_then(|json| self.serialization_profile().apply(json)){
             Ok(json) => {
-                self.post_to_info(routing_key.as_str(), json.as_str());
+                self.post_to_info(routing_key, json.as_str())?;
                 Ok(json)
             },
             Err(err) => Err(err)
         }
     }
 
-}
\ No newline at end of file
+    /// Set the status of a task and post its new state to the `info-topic` \
+    /// exchange in one go, so the update that is posted can't lag behind the \
+    /// task. The routing key is the task's key from the \
+    /// `routing_strategy()` (`<job_id>.<engine>.<frame>` by default) with a \
+    /// trailing `task` word, so it still starts with the job id for \
+    /// `watch_job()` and `parse_info_event()` reads it as a `TaskUpdate`. If \
+    /// posting fails the task keeps its new status. Get the serialized json \
+    /// back for debouncing:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use bender_job::task::TaskStatus;
+    /// # fn example(channel: &mut Channel, task: &mut bender_job::task::Task){
+    /// channel.update_and_post_task(task, TaskStatus::Running).expect("Couldn't post task update");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn update_and_post_task(&mut self, task: &mut Task, new_status: TaskStatus) -> GenResult<String>{
+        task.status = new_status;
+        let routing_key = RoutingKey::new(format!("{}.task", self.routing_strategy().task_key(task)))?;
+        self.post_task_info(task, routing_key)
+    }
+
+    /// Convert a task into the protobuf message `M` and post it to the \
+    /// `work` exchange like `post_task()`, tagged with the \
+    /// `application/x-protobuf` content type. Consumers decode it with \
+    /// `decode_proto()`. Only available with the `protobuf` feature:
+    /// ```ignore
+    /// // TaskProto is generated by prost from the shared task.proto
+    /// impl<'a> From<&'a Task> for TaskProto{ ... }
+    /// channel.post_task_proto::<TaskProto>(&task).expect("Couldn't post task");
+    /// ```
+    #[cfg(feature = "protobuf")]
+    fn post_task_proto<M>(&mut self, task: &Task) -> GenResult<()> where M: prost::Message + for<'a> From<&'a Task>{
+        let exchange = self.work_exchange();
+        let routing_key = RoutingKey::new(self.routing_strategy().task_key(task))?;
+        let properties = BasicProperties{ content_type: Some(proto::CONTENT_TYPE.to_string()), ..Default::default()};
+        let message = proto::encode(&M::from(task))?;
+        self.publish(exchange.as_str(), routing_key.as_str(), properties, message)
+    }
+
+    /// Serialize all tasks of a job into a single json array and post it to \
+    /// the `info-topic` exchange with the routing key `job.<id>.tasks`, so \
+    /// observers get the whole task set at once instead of one message per \
+    /// task. Task lists that would exceed the maximum message size are split \
+    /// into several arrays posted under the same key:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(job: &bender_job::Job, tasks: &[bender_job::task::Task]){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.post_tasks_info(tasks, job.id()).expect("Couldn't post tasks");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn post_tasks_info<S>(&mut self, tasks: &[Task], job_id: S) -> GenResult<()> where S: Into<String>{
+        let exchange = "info-topic";
+        let routing_key = format!("job.{}.tasks", job_id.into());
+        let profile = self.serialization_profile();
+        let serialized = tasks.iter().map(|task| task.serialize().and_then(|json| profile.apply(json))).collect::<GenResult<Vec<String>>>()?;
+        for chunk in batch::json_array_chunks(&serialized, batch::MAX_MESSAGE_SIZE) {
+            let properties = BasicProperties{ content_type: Some("application/json".to_string()), ..Default::default()};
+            self.publish(exchange, routing_key.as_str(), properties, chunk.into_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Submit a job together with its tasks as one traceable unit: the job \
+    /// is posted to the `job` queue like `post_job()`, each task to the work \
+    /// exchange like `post_task()`, and the job update and task list to the \
+    /// `info-topic` exchange like `post_job_info()` and `post_tasks_info()`. \
+    /// Every one of these messages carries the same `correlation_id` \
+    /// property, either the given one or a new one from \
+    /// `new_correlation_id()`. Returns the correlation id, so it can be \
+    /// logged and used to group the messages later:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(job: &bender_job::Job, tasks: &[bender_job::task::Task]){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let correlation_id = channel.submit_correlated(job, tasks, None).expect("Couldn't submit job");
+    /// println!("Submitted job {} as {}", job.id(), correlation_id);
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn submit_correlated(&mut self, job: &Job, tasks: &[Task], correlation_id: Option<String>) -> GenResult<String>{
+        let correlation_id = correlation_id.unwrap_or_else(properties::new_correlation_id);
+        let text = BasicProperties{ content_type: Some("text".to_string()), correlation_id: Some(correlation_id.clone()), ..Default::default()};
+        let json = self.serialization_profile().apply(job.serialize()?)?;
+        self.publish("", "job", text.clone(), json.clone().into_bytes())?;
+        let routing_key = RoutingKey::new(self.routing_strategy().job_info_key(job))?;
+        self.publish("info-topic", routing_key.as_str(), text.clone(), json.into_bytes())?;
+        let exchange = self.work_exchange();
+        let mut serialized = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let json = self.serialization_profile().apply(task.serialize()?)?;
+            let routing_key = RoutingKey::new(self.routing_strategy().task_key(task))?;
+            self.publish(exchange.as_str(), routing_key.as_str(), text.clone(), json.clone().into_bytes())?;
+            serialized.push(json);
+        }
+        if !serialized.is_empty() {
+            let routing_key = format!("job.{}.tasks", job.id());
+            for chunk in batch::json_array_chunks(&serialized, batch::MAX_MESSAGE_SIZE) {
+                let properties = BasicProperties{ content_type: Some("application/json".to_string()), correlation_id: Some(correlation_id.clone()), ..Default::default()};
+                self.publish("info-topic", routing_key.as_str(), properties, chunk.into_bytes())?;
+            }
+        }
+        Ok(correlation_id)
+    }
+
+    /// Subscribe to any queue by name and call the callback with every raw \
+    /// `Delivery` that arrives. Each delivery is acknowledged after the \
+    /// callback returned. This blocks until the connection is closed or the \
+    /// broker cancels the consumer (e.g. because the queue was deleted), in \
+    /// which case a `BenderMqError::ConsumerCancelled` error is returned:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.consume_raw("work", |delivery|{
+    ///     println!("Got {} bytes via {}", delivery.body.len(), delivery.routing_key);
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_raw<S, F>(&mut self, queue: S, callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        self.consume_with_arguments(queue, Table::new(), callback)
+    }
+
+    /// Subscribe to a queue like `consume_raw()`, but as its only consumer, \
+    /// e.g. for a processor of which only a single instance may run. The \
+    /// broker refuses the subscription while another consumer is subscribed \
+    /// and refuses every other consumer while this one is, so the first \
+    /// instance to subscribe becomes the leader. The others get a \
+    /// `BenderMqError::QueueInUse` error right away instead of sharing the \
+    /// messages. Their channel is closed then, open a new one to try again:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, BenderMqError};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let result = channel.consume_exclusive("job", |delivery|{
+    ///     println!("Leading with job {}", String::from_utf8_lossy(&delivery.body));
+    /// });
+    /// if let Err(err) = result {
+    ///     if let Some(&BenderMqError::QueueInUse(_)) = err.downcast_ref::<BenderMqError>() {
+    ///         println!("Another instance is the leader");
+    ///     }
+    /// }
+    /// ```
+    fn consume_exclusive<S, F>(&mut self, queue: S, callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        self.start_consumer_with(queue, Table::new(), true, callback)?;
+        self.run_consumers()
+    }
+
+    /// Like `consume_raw()`, but passes the given arguments (e.g. \
+    /// `x-priority` or `x-stream-offset`) to the broker when subscribing. \
+    /// They are handed through as they are, so any consumer argument the \
+    /// broker understands can be used. The arguments are kept with the \
+    /// consumer, so `Connection::resume()` subscribes with them again:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use amqp::{Table, TableEntry};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let mut arguments = Table::new();
+    /// // Gets the messages before consumers with a lower priority do
+    /// arguments.insert("x-priority".to_string(), TableEntry::LongInt(10));
+    /// channel.consume_with_arguments("work", arguments, |delivery|{
+    ///     println!("Got {} bytes via {}", delivery.body.len(), delivery.routing_key);
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_with_arguments<S, F>(&mut self, queue: S, arguments: Table, callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        self.start_consumer(queue, arguments, callback)?;
+        self.run_consumers()
+    }
+
+    /// Subscribe to any queue by name like `consume_with_arguments()`, but \
+    /// return the consumer tag right away instead of blocking. The \
+    /// deliveries are handed to the callback while the channel is read, e.g. \
+    /// by `run_consumers()` or `next_frame()`
+    fn start_consumer<S, F>(&mut self, queue: S, arguments: Table, callback: F) -> GenResult<String> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        self.start_consumer_with(queue, arguments, false, callback)
+    }
+
+    /// Like `start_consumer()`, but with `exclusive` set the broker only \
+    /// accepts this consumer if no other one is subscribed to the queue, and \
+    /// refuses every other consumer for as long as this one exists. A \
+    /// refused exclusive consumer returns a `BenderMqError::QueueInUse` \
+    /// error, after which the broker closed the channel (see \
+    /// `consume_exclusive()`)
+    fn start_consumer_with<S, F>(&mut self, queue: S, arguments: Table, exclusive: bool, mut callback: F) -> GenResult<String> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        let queue = queue.into();
+        let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, properties: BasicProperties, body: Vec<u8>|{
+            let delivery_tag = deliver.delivery_tag;
+            callback(Delivery::new(deliver, properties, body));
+            if let Err(err) = channel.basic_ack(delivery_tag, false) {
+                println!("Error: Couldn't acknowledge delivery {}: {}", delivery_tag, err)
+            }
+        };
+        // callback, queue, consumer_tag, no_local, no_ack, exclusive, nowait, arguments
+        let consumer_tag = match self.channel().basic_consume(consumer, queue.clone(), "".to_string(), false, false, exclusive, false, arguments.clone()) {
+            Ok(consumer_tag) => consumer_tag,
+            // The amqp crate drops the reply code of the channel.close, but an \
+            // exclusive consume is almost always refused for another consumer
+            Err(AMQPError::Protocol(ref reason)) if exclusive && reason.contains("channel.close") => {
+                return Err(Box::new(BenderMqError::QueueInUse(queue)));
+            },
+            Err(err) => return Err(Box::new(BenderMqError::from(err)))
+        };
+        self.record_consumer(consumer_tag.as_str(), queue.as_str(), &arguments, false, exclusive);
+        Ok(consumer_tag)
+    }
+
+    /// Subscribe to any queue by name and deserialize the json of every \
+    /// delivery before handing it to the callback together with the raw \
+    /// `Delivery`. Successfully handled messages are acknowledged. Messages \
+    /// that can't be deserialized, e.g. because their `content_encoding` \
+    /// isn't supported, are logged and nacked without requeueing, \
+    /// so a malformed message goes to the dead letter exchange (if the queue \
+    /// has one) instead of wedging the consumer:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use bender_job::task::Task;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.consume_json("work", |task: Task, delivery|{
+    ///     println!("Got a task via {}: {:?}", delivery.routing_key, task);
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_json<S, T, F>(&mut self, queue: S, callback: F) -> GenResult<()> where S: Into<String>, T: DeserializeOwned, F: FnMut(T, Delivery) + Send + 'static{
+        self.consume_json_validated(queue, |_: &T| Ok(()), callback)
+    }
+
+    /// Like `consume_json()`, but run the validator on every deserialized \
+    /// value before handing it to the callback. Values the validator returns \
+    /// an error for are logged with that reason and nacked without \
+    /// requeueing, just like messages that can't be deserialized. This way a \
+    /// worker never starts rendering an orphaned or malformed task:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use bender_job::task::Task;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let known_jobs = vec!["5ec8dfa8a14b7a39".to_string()];
+    /// channel.consume_json_validated("work", move |task: &Task|{
+    ///     let json = task.serialize()?;
+    ///     match known_jobs.iter().any(|id| json.contains(id.as_str())) {
+    ///         true => Ok(()),
+    ///         false => Err(From::from("Task doesn't belong to a known job"))
+    ///     }
+    /// }, |task: Task, _|{
+    ///     println!("Rendering {:?}", task);
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_json_validated<S, T, V, F>(&mut self, queue: S, validator: V, callback: F) -> GenResult<()> where S: Into<String>, T: DeserializeOwned, V: Fn(&T) -> GenResult<()> + Send + 'static, F: FnMut(T, Delivery) + Send + 'static{
+        self.consume_json_with_arguments(queue, Table::new(), validator, callback)
+    }
+
+    /// Like `consume_json_validated()`, but passes the given arguments to \
+    /// the broker when subscribing (see `consume_with_arguments()`)
+    fn consume_json_with_arguments<S, T, V, F>(&mut self, queue: S, arguments: Table, validator: V, mut callback: F) -> GenResult<()> where S: Into<String>, T: DeserializeOwned, V: Fn(&T) -> GenResult<()> + Send + 'static, F: FnMut(T, Delivery) + Send + 'static{
+        let queue = queue.into();
+        let queue_name = queue.clone();
+        let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, properties: BasicProperties, body: Vec<u8>|{
+            let delivery_tag = deliver.delivery_tag;
+            let value = delivery::decode_json::<T>(&properties, &body)
+                .map_err(|err| format!("couldn't be deserialized ({})", err))
+                .and_then(|value| match validator(&value) {
+                    Ok(()) => Ok(value),
+                    Err(err) => Err(format!("is invalid ({})", err))
+                });
+            match value {
+                Ok(value) => {
+                    callback(value, Delivery::new(deliver, properties, body));
+                    if let Err(err) = channel.basic_ack(delivery_tag, false) {
+                        println!("Error: Couldn't acknowledge delivery {}: {}", delivery_tag, err)
+                    }
+                },
+                Err(reason) => {
+                    println!("Error: Dropping message from {} that {}: {}", queue_name, reason, String::from_utf8_lossy(&body));
+                    // delivery_tag, multiple, requeue
+                    if let Err(err) = channel.basic_nack(delivery_tag, false, false) {
+                        println!("Error: Couldn't nack delivery {}: {}", delivery_tag, err)
+                    }
+                }
+            }
+        };
+        subscribe(self, consumer, queue, arguments, false)?;
+        self.run_consumers()
+    }
+
+    /// Subscribe to any queue by name and leave settling each delivery to \
+    /// the callback, which gets an `AckHandle` bound to this channel and \
+    /// delivery along with it. This blocks like `consume_raw()`:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.consume_with_ack("work", |delivery, handle|{
+    ///     let result = match delivery.body.is_empty() {
+    ///         true => handle.nack(false),
+    ///         false => handle.ack()
+    ///     };
+    ///     if let Err(err) = result { println!("Error: {}", err); }
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_with_ack<S, F>(&mut self, queue: S, callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery, AckHandle) + Send + 'static{
+        self.consume_with_ack_and_arguments(queue, Table::new(), callback)
+    }
+
+    /// Like `consume_with_ack()`, but passes the given arguments to the \
+    /// broker when subscribing (see `consume_with_arguments()`)
+    fn consume_with_ack_and_arguments<S, F>(&mut self, queue: S, arguments: Table, mut callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery, AckHandle) + Send + 'static{
+        let queue = queue.into();
+        let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, properties: BasicProperties, body: Vec<u8>|{
+            let delivery_tag = deliver.delivery_tag;
+            callback(Delivery::new(deliver, properties, body), AckHandle::new(channel, delivery_tag));
+        };
+        subscribe(self, consumer, queue, arguments, false)?;
+        self.run_consumers()
+    }
+
+    /// Subscribe to any queue by name without acknowledgements: the broker \
+    /// considers every message handled as soon as it has been sent. This \
+    /// saves a round trip per message, but messages that were sent to a \
+    /// consumer that crashes are lost, so only use it where that is \
+    /// acceptable (e.g. the info updates). Blocks like `consume_raw()`, \
+    /// which is the acknowledging counterpart:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.consume_no_ack("info", |delivery|{
+    ///     println!("{}: {}", delivery.routing_key, String::from_utf8_lossy(&delivery.body));
+    /// }).expect("Consuming from info failed");
+    /// ```
+    fn consume_no_ack<S, F>(&mut self, queue: S, callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        self.consume_no_ack_with_arguments(queue, Table::new(), callback)
+    }
+
+    /// Like `consume_no_ack()`, but passes the given arguments to the \
+    /// broker when subscribing (see `consume_with_arguments()`)
+    fn consume_no_ack_with_arguments<S, F>(&mut self, queue: S, arguments: Table, mut callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        let queue = queue.into();
+        let consumer = move |_: &mut Channel, deliver: protocol::basic::Deliver, properties: BasicProperties, body: Vec<u8>|{
+            callback(Delivery::new(deliver, properties, body));
+        };
+        subscribe(self, consumer, queue, arguments, true)?;
+        self.run_consumers()
+    }
+
+    /// Subscribe to any queue by name like `consume_raw()`, but check the \
+    /// `content-hash` header of every delivery against its body first (see \
+    /// `PublishOptions::content_hash`). Deliveries whose body doesn't match \
+    /// their hash are logged and nacked without requeueing, so they go to \
+    /// the dead letter exchange (if the queue has one) instead of the \
+    /// callback. Deliveries without a hash are handed over unless \
+    /// `require_hash` is set, in which case they are rejected the same way \
+    /// (see `consume_verified_with_arguments()` to pass consumer arguments):
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Connection, BenderMQ, PublishOptions};
+    /// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+    /// connection.set_publish_options(PublishOptions{ content_hash: true, ..Default::default() });
+    /// connection.post_to_job("{}").expect("Couldn't post job");
+    /// connection.consume_verified("job", true, |delivery|{
+    ///     println!("Got an intact job of {} bytes", delivery.body.len());
+    /// }).expect("Consuming from job failed");
+    /// ```
+    fn consume_verified<S, F>(&mut self, queue: S, require_hash: bool, callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        self.consume_verified_with_arguments(queue, Table::new(), require_hash, callback)
+    }
+
+    /// Like `consume_verified()`, but passes the given arguments to the \
+    /// broker when subscribing (see `consume_with_arguments()`)
+    fn consume_verified_with_arguments<S, F>(&mut self, queue: S, arguments: Table, require_hash: bool, mut callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        let queue = queue.into();
+        let queue_name = queue.clone();
+        let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, properties: BasicProperties, body: Vec<u8>|{
+            let delivery_tag = deliver.delivery_tag;
+            let rejection = match integrity::verify_content_hash(&properties, &body) {
+                Some(true) => None,
+                Some(false) => Some("doesn't match its content hash"),
+                None if require_hash => Some("has no content hash"),
+                None => None
+            };
+            match rejection {
+                None => {
+                    callback(Delivery::new(deliver, properties, body));
+                    if let Err(err) = channel.basic_ack(delivery_tag, false) {
+                        println!("Error: Couldn't acknowledge delivery {}: {}", delivery_tag, err)
+                    }
+                },
+                Some(reason) => {
+                    println!("Error: Dropping message from {} that {}", queue_name, reason);
+                    // delivery_tag, multiple, requeue
+                    if let Err(err) = channel.basic_nack(delivery_tag, false, false) {
+                        println!("Error: Couldn't nack delivery {}: {}", delivery_tag, err)
+                    }
+                }
+            }
+        };
+        subscribe(self, consumer, queue, arguments, false)?;
+        self.run_consumers()
+    }
+
+    /// Subscribe to any queue by name like `consume_raw()` and count every \
+    /// delivery in the given `Throughput` once the callback returned. Keep a \
+    /// clone of it to read the messages per second and the total from \
+    /// elsewhere, e.g. for logging:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, Throughput};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let throughput = Throughput::new(Duration::from_secs(60));
+    /// let stats = throughput.clone();
+    /// channel.consume_with_throughput("work", &throughput, move |_delivery|{
+    ///     println!("{:.1} tasks/s, {} in total", stats.throughput(), stats.total());
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_with_throughput<S, F>(&mut self, queue: S, throughput: &Throughput, mut callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery) + Send + 'static{
+        let throughput = throughput.clone();
+        self.consume_raw(queue, move |delivery|{
+            callback(delivery);
+            throughput.record();
+        })
+    }
+
+    /// Consume the `work` queue on a new thread and forward every task into \
+    /// the returned `Receiver`, so tasks can be pulled with `recv()` instead \
+    /// of being pushed into a callback. The broker hands out one task at a \
+    /// time and each task is acknowledged only once it was taken from the \
+    /// `Receiver`, so tasks don't pile up in memory: if the process dies \
+    /// before a task is taken, the broker delivers it again. A task that was \
+    /// taken counts as done, even if the process dies while working on it. \
+    /// Messages that aren't a valid task are nacked without requeueing and \
+    /// forwarded as an `Err` with the reason. The errors are \
+    /// `String`s, as a `GenError` can't be sent between threads. If the \
+    /// consumer stops, its error is forwarded before the thread ends. \
+    /// Dropping the `Receiver` cancels the consumer with the next delivery, \
+    /// which is requeued, and ends the thread:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let (tasks, handle) = channel.task_receiver();
+    /// for task in tasks.iter() {
+    ///     match task {
+    ///         Ok(task) => println!("Got a task: {:?}", task),
+    ///         Err(err) => println!("Error: {}", err)
+    ///     }
+    /// }
+    /// handle.join().expect("Consumer thread panicked");
+    /// ```
+    fn task_receiver(mut self) -> (Receiver<Result<Task, String>>, JoinHandle<()>) where Self: Sized + Send + 'static{
+        // Without a buffer every send waits until the task is taken
+        let (sender, receiver) = mpsc::sync_channel(0);
+        let handle = std::thread::spawn(move || {
+            let forward = sender.clone();
+            let mut cancelled = false;
+            let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, properties: BasicProperties, body: Vec<u8>|{
+                let delivery_tag = deliver.delivery_tag;
+                let task = delivery::decode_json::<Task>(&properties, &body)
+                    .map_err(|err| format!("Dropping message from work that couldn't be deserialized ({}): {}", err, String::from_utf8_lossy(&body)));
+                let valid = task.is_ok();
+                let result = if forward.send(task).is_err() {
+                    // Nobody listens anymore, give the task back and stop consuming
+                    if !cancelled {
+                        cancelled = true;
+                        let cancel = protocol::basic::Cancel{ consumer_tag: deliver.consumer_tag.clone(), nowait: false };
+                        if let Err(err) = channel.send_method_frame(&cancel) {
+                            println!("Error: Couldn't cancel consumer {}: {}", deliver.consumer_tag, err)
+                        }
+                    }
+                    // delivery_tag, multiple, requeue
+                    channel.basic_nack(delivery_tag, false, true)
+                } else if valid {
+                    channel.basic_ack(delivery_tag, false)
+                } else {
+                    channel.basic_nack(delivery_tag, false, false)
+                };
+                if let Err(err) = result {
+                    println!("Error: Couldn't acknowledge delivery {}: {}", delivery_tag, err)
+                }
+            };
+            let consumed = self.set_prefetch(TASK_RECEIVER_PREFETCH, None)
+                .and_then(|_| subscribe(&mut self, consumer, "work".to_string(), Table::new(), false))
+                .and_then(|_| {
+                    loop {
+                        let frame = self.next_frame()?;
+                        match frames::method_name(&frame) {
+                            Some("basic.cancel-ok") => return Ok(()),
+                            Some("basic.cancel") => {
+                                let cancel: protocol::basic::Cancel = frames::decode(&frame)?;
+                                return Err(Box::new(BenderMqError::ConsumerCancelled(cancel.consumer_tag)) as GenError);
+                            },
+                            _ => ()
+                        }
+                    }
+                });
+            if let Err(err) = consumed {
+                let _ = sender.send(Err(err.to_string()));
+            }
+        });
+        (receiver, handle)
+    }
+
+    /// Follow the updates of a single job. A temporary queue is bound to the \
+    /// `info-topic` exchange with the keys `<job_id>.#` and `job.<job_id>.#` \
+    /// and every message on it is forwarded as an `InfoEvent`. With the \
+    /// `DefaultRoutingStrategy` this covers the job updates of \
+    /// `post_job_info()` (the plain job id), the patches of \
+    /// `post_job_delta()` (`<job_id>.delta`), the task updates of \
+    /// `update_and_post_task()` (`<job_id>.<engine>.<frame>.task`), the \
+    /// task lists of `post_tasks_info()` (`job.<job_id>.tasks`) and the final \
+    /// update of `post_job_complete()` (`job.<job_id>.complete`). Keys of a \
+    /// custom `routing_strategy()` that don't start with the job id aren't \
+    /// covered. Messages that don't parse are logged and skipped. Like with \
+    /// `start_consumer()` the events arrive while the channel is read, e.g. \
+    /// by `run_consumers()` on another thread. Once the `Receiver` is \
+    /// dropped the consumer is cancelled with the next message, which makes \
+    /// the broker delete the temporary queue:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let events = channel.watch_job("9ac9b18f5e6d4f329acda411e3de8cde").expect("Couldn't watch job");
+    /// std::thread::spawn(move || channel.run_consumers().map_err(|err| err.to_string()));
+    /// for event in events.iter() {
+    ///     println!("{:?}", event);
+    /// }
+    /// ```
+    fn watch_job<S>(&mut self, job_id: S) -> GenResult<Receiver<InfoEvent>> where S: Into<String>{
+        let job_id = job_id.into();
+        let queue = self.declare_anonymous_queue()?;
+        for routing_key in &[format!("{}.#", job_id), format!("job.{}.#", job_id)] {
+            // queue: S, exchange: S, routing_key: S, nowait: bool,a rguments: Table
+            self.channel().queue_bind(queue.as_str(), "info-topic", routing_key.as_str(), false, Table::new()).map_err(BenderMqError::from)?;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let mut cancelled = false;
+        let consumer = move |channel: &mut Channel, deliver: protocol::basic::Deliver, _properties: BasicProperties, body: Vec<u8>|{
+            if cancelled { return; }
+            match parse_info_event(deliver.routing_key.as_str(), &body) {
+                Ok(event) => if sender.send(event).is_err() {
+                    // Nobody listens anymore, the broker deletes the auto-delete queue with its last consumer
+                    cancelled = true;
+                    let cancel = protocol::basic::Cancel{ consumer_tag: deliver.consumer_tag.clone(), nowait: true };
+                    if let Err(err) = channel.send_method_frame(&cancel) {
+                        println!("Error: Couldn't cancel consumer {}: {}", deliver.consumer_tag, err)
+                    }
+                },
+                Err(err) => println!("Error: Skipping info update {} that couldn't be parsed: {}", deliver.routing_key, err)
+            }
+        };
+        subscribe(self, consumer, queue, Table::new(), true)?;
+        Ok(receiver)
+    }
+
+    /// Serialize a job and post it to the `job` queue like `post_job()`, \
+    /// with the given trace context in the `traceparent` header. Only \
+    /// available with the `tracing` feature:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # extern crate bender_job;
+    /// # use bender_mq::{Channel, BenderMQ, TraceContext};
+    /// # fn example(job: &bender_job::Job){
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let context = TraceContext::new_root();
+    /// channel.post_job_traced(job, &context).expect("Couldn't post job");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    #[cfg(feature = "tracing")]
+    fn post_job_traced(&mut self, job: &Job, context: &TraceContext) -> GenResult<String>{
+        let json = self.serialization_profile().apply(job.serialize()?)?;
+        let routing_key = "job";
+        let mut headers = Table::new();
+        context.inject(&mut headers);
+        let properties = BasicProperties{ content_type: Some("text".to_string()), headers: Some(headers), ..Default::default()};
+        self.publish("", routing_key, properties, json.clone().into_bytes())?;
+        Ok(json)
+    }
+
+    /// Subscribe to any queue by name like `consume_raw()` and hand every \
+    /// delivery to the callback together with a child of the trace context \
+    /// it was published with. Deliveries without a trace context start a new \
+    /// trace. Only available with the `tracing` feature:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.consume_traced("job", |delivery, context|{
+    ///     println!("Handling {} bytes in span {}", delivery.body.len(), context.to_traceparent());
+    /// }).expect("Consuming from job failed");
+    /// ```
+    #[cfg(feature = "tracing")]
+    fn consume_traced<S, F>(&mut self, queue: S, mut callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(Delivery, TraceContext) + Send + 'static{
+        self.consume_raw(queue, move |delivery|{
+            let context = delivery.properties.headers.as_ref()
+                .and_then(TraceContext::from_headers)
+                .map(|parent| parent.child())
+                .unwrap_or_else(TraceContext::new_root);
+            callback(delivery, context);
+        })
+    }
+
+    /// Block while the consumers on this channel get their deliveries. \
+    /// Returns when the connection is closed or with a \
+    /// `BenderMqError::ConsumerCancelled` error when the broker cancelled one \
+    /// of the consumers
+    fn run_consumers(&mut self) -> GenResult<()>{
+        loop {
+            // Deliveries are dispatched to the consumer while reading
+            let frame = self.next_frame()?;
+            if frames::method_name(&frame) == Some("basic.cancel") {
+                let cancel: protocol::basic::Cancel = frames::decode(&frame)?;
+                return Err(Box::new(BenderMqError::ConsumerCancelled(cancel.consumer_tag)));
+            }
+        }
+    }
+
+    /// Ask the broker to redeliver all messages on this channel that haven't \
+    /// been acknowledged yet. With `requeue` set they go back into their \
+    /// queue and may be delivered to any consumer, otherwise they are \
+    /// redelivered to the consumers on this channel:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.recover(true).expect("Couldn't recover unacknowledged messages");
+    /// ```
+    fn recover(&mut self, requeue: bool) -> GenResult<()>{
+        let _: protocol::basic::RecoverOk = self.channel().rpc(&protocol::basic::Recover{ requeue }, "basic.recover-ok").map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Acknowledge every unacknowledged delivery on this channel up to and \
+    /// including the given delivery tag in a single round trip. Consumers \
+    /// that handle messages in order can ack e.g. every 100 messages instead \
+    /// of each one. Messages handled since the last ack are delivered again \
+    /// if the consumer crashes before acking, so handling has to be \
+    /// idempotent:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(channel: &mut Channel, last_handled_tag: u64){
+    /// channel.ack_up_to(last_handled_tag).expect("Couldn't acknowledge deliveries");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn ack_up_to(&mut self, delivery_tag: u64) -> GenResult<()>{
+        // delivery_tag, multiple
+        self.channel().basic_ack(delivery_tag, true).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Reject a single delivery with `basic.reject`. With `requeue` set the \
+    /// broker puts the message back into its queue, otherwise it is dropped \
+    /// or dead lettered. Unlike `basic.nack`, which can reject all deliveries \
+    /// up to a tag at once, this always covers exactly one message, which is \
+    /// what some tooling expects:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # fn example(channel: &mut Channel, delivery_tag: u64){
+    /// channel.reject(delivery_tag, false).expect("Couldn't reject delivery");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn reject(&mut self, delivery_tag: u64, requeue: bool) -> GenResult<()>{
+        // delivery_tag, requeue
+        self.channel().basic_reject(delivery_tag, requeue).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Limit how many unacknowledged messages the broker delivers to the \
+    /// consumers on this channel. With a `size` in bytes the broker also \
+    /// stops delivering once the unacknowledged bodies add up to that size, \
+    /// so a worker can prefetch by memory budget when task sizes vary a lot \
+    /// (up to 4 GB, the field is 32 bit). \
+    /// A `count` of 0 means no limit on the number of messages. Has to be \
+    /// called before consuming:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// // at most 8 tasks and at most 1 GB of them
+    /// channel.set_prefetch(8, Some(1024 * 1024 * 1024)).expect("Couldn't set prefetch");
+    /// ```
+    fn set_prefetch(&mut self, count: u16, size: Option<u32>) -> GenResult<()>{
+        // prefetch_size, prefetch_count, global
+        self.channel().basic_qos(size.unwrap_or(0), count, false).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Limit how many unacknowledged messages all consumers on this channel \
+    /// may hold together. Note the difference to `set_prefetch()`: RabbitMQ \
+    /// applies that limit to each consumer on its own, so two consumers with \
+    /// a prefetch of 10 may hold 20 messages. This limit is shared, the same \
+    /// two consumers hold at most 10 between them. It is not shared with \
+    /// other channels, despite the name of the `global` flag it sets. Both \
+    /// limits can be combined and a `count` of 0 means no shared limit. Has \
+    /// to be called before consuming:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// // each consumer holds at most 4 tasks, all of them at most 10
+    /// channel.set_prefetch(4, None).expect("Couldn't set prefetch");
+    /// channel.set_prefetch_global(10).expect("Couldn't set prefetch");
+    /// ```
+    fn set_prefetch_global(&mut self, count: u16) -> GenResult<()>{
+        // prefetch_size, prefetch_count, global
+        self.channel().basic_qos(0, count, true).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Reject a delivery and record why. The message is posted to the \
+    /// `dead-letter` exchange with its original routing key, properties and \
+    /// body, plus the headers `x-rejection-reason`, `x-original-exchange` \
+    /// and `x-original-routing-key`, then the delivery is acknowledged. \
+    /// Unlike a plain `basic_nack` the dead letter consumer can tell why a \
+    /// task failed. This needs the whole `Delivery` instead of just its tag, \
+    /// as the broker only hands out the content once:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, Delivery};
+    /// # fn example(channel: &mut Channel, delivery: &Delivery){
+    /// channel.nack_with_reason(delivery, "missing asset").expect("Couldn't reject delivery");
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn nack_with_reason(&mut self, delivery: &Delivery, reason: &str) -> GenResult<()>{
+        let exchange = "dead-letter";
+        let mut properties = delivery.properties.clone();
+        let mut headers = properties.headers.take().unwrap_or_else(Table::new);
+        headers.insert("x-rejection-reason".to_string(), TableEntry::LongString(reason.to_string()));
+        headers.insert("x-original-exchange".to_string(), TableEntry::LongString(delivery.exchange.clone()));
+        headers.insert("x-original-routing-key".to_string(), TableEntry::LongString(delivery.routing_key.clone()));
+        properties.headers = Some(headers);
+        self.publish(exchange, delivery.routing_key.as_str(), properties, delivery.body.clone())?;
+        // Acknowledged instead of nacked, so a dead letter exchange configured
+        // on the queue doesn't get the message a second time
+        self.channel().basic_ack(delivery.delivery_tag, false).map_err(BenderMqError::from)?;
+        Ok(())
+    }
+
+    /// Declare a stream queue named `info-stream`. This queue will be bound \
+    /// to the exchange named `info-topic` and keeps the info updates after \
+    /// they have been read, so they can be replayed with `consume_info_stream()`.
+    fn create_info_stream(&mut self) -> GenResult<()>{
+        let queue_name = stream::INFO_STREAM;
+        let exchange_name = "info-topic";
+        if self.is_declared(format!("queue {}", queue_name).as_str()) { return Ok(()); }
+        // Streams have to be durable, not exclusive and not auto deleted
+        self.channel().queue_declare(queue_name, false, true, false, false, false, stream::queue_arguments())?;
+        self.channel().queue_bind(queue_name, exchange_name, "#", false, Table::new())?;
+        self.record_declared(format!("queue {}", queue_name).as_str());
+        Ok(())
+    }
+
+    /// Read the `info-stream` starting at the given offset and call the \
+    /// callback with every delivery. A dashboard that just connected can \
+    /// replay the updates of the last ten minutes like this:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, StreamOffset};
+    /// # use std::time::{SystemTime, UNIX_EPOCH};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.create_info_stream().expect("Declaration of info-stream failed");
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    /// channel.consume_info_stream(StreamOffset::Timestamp(now - 600), |delivery|{
+    ///     println!("{}: {}", delivery.routing_key, String::from_utf8_lossy(&delivery.body));
+    /// }).expect("Consuming from info-stream failed");
+    /// ```
+    fn consume_info_stream<F>(&mut self, offset: StreamOffset, callback: F) -> GenResult<()> where F: FnMut(Delivery) + Send + 'static{
+        // prefetch_size, prefetch_count, global
+        self.channel().basic_qos(0, stream::STREAM_PREFETCH, false).map_err(BenderMqError::from)?;
+        self.consume_with_arguments(stream::INFO_STREAM, stream::consumer_arguments(offset), callback)
+    }
+
+    /// Read a stream queue (e.g. `info-stream`) and keep track of the \
+    /// offset of every message handed to the callback (see `OffsetTracker`). \
+    /// Reading starts right after the offset the store holds, or at \
+    /// `default` if it holds none. Every `commit_every` messages, and when \
+    /// the consumer ends with its channel, the offset of the last handled \
+    /// message is written to the store. After a restart or a reconnect (see \
+    /// `Connection::reconnect()`) call this again with a store that reads \
+    /// from the same place, and the consumer continues where it left off \
+    /// instead of replaying the whole stream:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, FileOffsetStore, StreamOffset};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.create_info_stream().expect("Declaration of info-stream failed");
+    /// let store = FileOffsetStore::new("/var/lib/bender/info-stream.offset");
+    /// channel.consume_stream_tracked("info-stream", store, 100, StreamOffset::First, |delivery|{
+    ///     println!("{}: {}", delivery.routing_key, String::from_utf8_lossy(&delivery.body));
+    /// }).expect("Consuming from info-stream failed");
+    /// ```
+    fn consume_stream_tracked<S, O, F>(&mut self, queue: S, store: O, commit_every: u64, default: StreamOffset, mut callback: F) -> GenResult<()> where S: Into<String>, O: OffsetStore + 'static, F: FnMut(Delivery) + Send + 'static{
+        let mut tracker = OffsetTracker::new(store, commit_every)?;
+        let offset = tracker.resume_offset(default);
+        // prefetch_size, prefetch_count, global
+        self.channel().basic_qos(0, stream::STREAM_PREFETCH, false).map_err(BenderMqError::from)?;
+        self.consume_with_arguments(queue, stream::consumer_arguments(offset), move |delivery|{
+            let offset = delivery.stream_offset();
+            // Streams start at the beginning of a chunk, which may hold messages before the offset
+            if let Some(offset) = offset {
+                if !tracker.is_new(offset) { return; }
+            }
+            callback(delivery);
+            if let Some(offset) = offset {
+                if let Err(err) = tracker.handled(offset) {
+                    println!("Error: Couldn't commit the stream offset {}: {}", offset, err);
+                }
+            }
+        })
+    }
+
+    /// Fetch up to `n` tasks from the `work` queue and acknowledge them. If \
+    /// fewer tasks are waiting, only these are returned instead of waiting \
+    /// for more. Messages that aren't a valid task are rejected:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let tasks = channel.consume_n_tasks(10).expect("Couldn't drain tasks");
+    /// assert!(tasks.len() <= 10);
+    /// ```
+    fn consume_n_tasks(&mut self, n: usize) -> GenResult<Vec<Task>>{
+        let mut tasks = Vec::new();
+        // basic_get returns nothing once the queue is empty instead of blocking
+        for result in self.channel().basic_get("work", false).take(n) {
+            match delivery::decode_json::<Task>(&result.headers, &result.body) {
+                Ok(task) => {
+                    result.ack();
+                    tasks.push(task);
+                },
+                Err(err) => {
+                    println!("Error: Rejecting message from work that isn't a task ({}): {}", err, String::from_utf8_lossy(&result.body));
+                    result.reject(false);
+                }
+            }
+        }
+        Ok(tasks)
+    }
+
+    /// Fetch everything waiting in the `work` queue, until it is empty or the \
+    /// deadline passed, for processing in a micro batch. The tasks are not \
+    /// acknowledged, each one comes with its delivery tag, so the whole batch \
+    /// can be acknowledged at once with `ack_up_to()` when it is done. If the \
+    /// channel closes before that, the broker delivers the tasks again. \
+    /// Messages that aren't a valid task are rejected right away without \
+    /// requeueing, so `ack_up_to()` never covers them and they go to the \
+    /// dead letter exchange (if the queue has one):
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::{Duration, Instant};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let tasks = channel.drain_tasks(Instant::now() + Duration::from_secs(2)).expect("Couldn't drain tasks");
+    /// for &(_, ref task) in &tasks {
+    ///     println!("Rendering {:?}", task);
+    /// }
+    /// if let Some(&(last_tag, _)) = tasks.last() {
+    ///     channel.ack_up_to(last_tag).expect("Couldn't acknowledge tasks");
+    /// }
+    /// ```
+    ///
+    /// A message that isn't a task doesn't come back once the channel that \
+    /// rejected it is gone, while the tasks that weren't acknowledged do:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # extern crate amqp;
+    /// # use bender_mq::{Channel, BenderMQ, RoutingKey};
+    /// # use amqp::Basic;
+    /// # use std::time::{Duration, Instant};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// channel.create_work_queue().expect("Declaration of work queue failed");
+    /// // Settle whatever earlier runs left in the queue
+    /// let waiting = channel.drain_tasks(Instant::now() + Duration::from_secs(2)).expect("Couldn't drain tasks");
+    /// if let Some(&(last_tag, _)) = waiting.last() {
+    ///     channel.ack_up_to(last_tag).expect("Couldn't acknowledge tasks");
+    /// }
+    ///
+    /// let routing_key = RoutingKey::new("broken").expect("Invalid routing key");
+    /// channel.post_to_work_routed(routing_key, "not a task").expect("Couldn't post");
+    /// let tasks = channel.drain_tasks(Instant::now() + Duration::from_secs(2)).expect("Couldn't drain tasks");
+    /// assert!(tasks.is_empty());
+    /// channel.close(200, "Bye").expect("Couldn't close channel");
+    ///
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// assert!(channel.basic_get("work", false).next().is_none());
+    /// ```
+    fn drain_tasks(&mut self, deadline: Instant) -> GenResult<Vec<(u64, Task)>>{
+        let mut tasks = Vec::new();
+        while Instant::now() < deadline {
+            // basic_get returns nothing once the queue is empty instead of blocking
+            let (delivery_tag, properties, body) = match self.channel().basic_get("work", false).next() {
+                Some(result) => (result.reply.delivery_tag, result.headers, result.body),
+                None => break
+            };
+            match delivery::decode_json::<Task>(&properties, &body) {
+                Ok(task) => tasks.push((delivery_tag, task)),
+                Err(err) => {
+                    println!("Error: Rejecting message from work that isn't a task ({}): {}", err, String::from_utf8_lossy(&body));
+                    // The GetIterator is gone already, so reject on the channel itself
+                    // delivery_tag, requeue
+                    self.channel().basic_reject(delivery_tag, false).map_err(BenderMqError::from)?;
+                }
+            }
+        }
+        Ok(tasks)
+    }
+
+    /// Take tasks from the `work-priority` queue and only fall back to the \
+    /// `work` queue while it is empty, so urgent tasks preempt everything \
+    /// that is already waiting. Each task is handed to the callback together \
+    /// with its `Delivery` and acknowledged once the callback returned. \
+    /// Messages that aren't a valid task are rejected. The queues are polled, \
+    /// when both are empty the next poll happens after `poll_interval`. This \
+    /// blocks until an error occurs:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.create_priority_work_queue().expect("Declaration of work-priority queue failed");
+    /// channel.consume_tasks_prioritized(Duration::from_millis(500), |task, delivery|{
+    ///     println!("Got a task via {}: {:?}", delivery.exchange, task);
+    /// }).expect("Consuming tasks failed");
+    /// ```
+    fn consume_tasks_prioritized<F>(&mut self, poll_interval: Duration, mut callback: F) -> GenResult<()> where F: FnMut(Task, Delivery){
+        loop {
+            let mut fetched = None;
+            for queue in &["work-priority", "work"] {
+                // basic_get returns nothing once the queue is empty instead of blocking
+                fetched = self.channel().basic_get(queue, false).next()
+                    .map(|result| Delivery::from_get(result.reply, result.headers, result.body));
+                if fetched.is_some() { break; }
+            }
+            let delivery = match fetched {
+                Some(delivery) => delivery,
+                None => {
+                    std::thread::sleep(poll_interval);
+                    continue;
+                }
+            };
+            let delivery_tag = delivery.delivery_tag;
+            match delivery.json::<Task>() {
+                Ok(task) => {
+                    callback(task, delivery);
+                    // delivery_tag, multiple
+                    self.channel().basic_ack(delivery_tag, false).map_err(BenderMqError::from)?;
+                },
+                Err(err) => {
+                    println!("Error: Rejecting message from {} that isn't a task ({}): {}", delivery.routing_key, err, String::from_utf8_lossy(&delivery.body));
+                    // delivery_tag, requeue
+                    self.channel().basic_reject(delivery_tag, false).map_err(BenderMqError::from)?;
+                }
+            }
+        }
+    }
+
+    /// Take messages from a queue and hand them to the callback. If it \
+    /// returns an error the message is requeued, until it has been \
+    /// redelivered `max_redeliveries` times (see \
+    /// `Delivery::redelivery_count()`). Then it is dead-lettered with the \
+    /// error as reason via `nack_with_reason()`, so a task that always fails \
+    /// can't keep a worker busy forever. To count the redeliveries the \
+    /// message is published again to the end of the queue with the \
+    /// `x-redelivery-count` header and the original acknowledged. The queue \
+    /// is polled every `poll_interval` while empty. This blocks until an \
+    /// error occurs:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_dead_letter_exchange().expect("Declaration of dead-letter exchange failed");
+    /// channel.consume_with_redelivery_limit("work", 5, Duration::from_millis(500), |delivery|{
+    ///     match delivery.body.is_empty() {
+    ///         true => Err(From::from("empty task")),
+    ///         false => Ok(())
+    ///     }
+    /// }).expect("Consuming from work failed");
+    /// ```
+    fn consume_with_redelivery_limit<S, F>(&mut self, queue: S, max_redeliveries: u64, poll_interval: Duration, mut callback: F) -> GenResult<()> where S: Into<String>, F: FnMut(&Delivery) -> GenResult<()>{
+        let queue = queue.into();
+        loop {
+            // basic_get returns nothing once the queue is empty instead of blocking
+            let fetched = self.channel().basic_get(queue.as_str(), false).next()
+                .map(|result| Delivery::from_get(result.reply, result.headers, result.body));
+            let delivery = match fetched {
+                Some(delivery) => delivery,
+                None => {
+                    std::thread::sleep(poll_interval);
+                    continue;
+                }
+            };
+            let err = match callback(&delivery) {
+                Ok(()) => {
+                    // delivery_tag, multiple
+                    self.channel().basic_ack(delivery.delivery_tag, false).map_err(BenderMqError::from)?;
+                    continue;
+                },
+                Err(err) => err
+            };
+            let redeliveries = delivery.redelivery_count();
+            if redeliveries >= max_redeliveries {
+                println!("Error: Dead-lettering message from {} after {} redeliveries: {}", queue, redeliveries, err);
+                self.nack_with_reason(&delivery, format!("failed {} times: {}", redeliveries + 1, err).as_str())?;
+            } else {
+                let mut properties = delivery.properties.clone();
+                let mut headers = properties.headers.take().unwrap_or_else(Table::new);
+                headers.insert(delivery::REDELIVERY_COUNT_HEADER.to_string(), TableEntry::LongLongInt((redeliveries + 1) as i64));
+                properties.headers = Some(headers);
+                self.publish("", queue.as_str(), properties, delivery.body.clone())?;
+                self.channel().basic_ack(delivery.delivery_tag, false).map_err(BenderMqError::from)?;
+            }
+        }
+    }
+
+    /// Drain up to `limit` messages (or all waiting ones) from the `info` \
+    /// queue and write each body as one line of json to the writer. Bodies \
+    /// that aren't json are written as a json string. Every message is \
+    /// acknowledged once its line has been written. Returns the number of \
+    /// written lines:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::fs::File;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let file = File::create("info.ndjson").expect("Couldn't create file");
+    /// let count = channel.export_info_ndjson(file, Some(1000)).expect("Couldn't export info");
+    /// println!("Exported {} messages", count);
+    /// ```
+    fn export_info_ndjson<W>(&mut self, mut writer: W, limit: Option<usize>) -> GenResult<usize> where W: Write{
+        let mut count = 0;
+        // basic_get returns nothing once the queue is empty instead of blocking
+        for result in self.channel().basic_get("info", false).take(limit.unwrap_or(usize::max_value())) {
+            let value = serde_json::from_slice::<serde_json::Value>(&result.body)
+                .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&result.body).into_owned()));
+            serde_json::to_writer(&mut writer, &value)?;
+            writer.write_all(b"\n")?;
+            result.ack();
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Serialize any value to json and post it to the `info-topic` exchange \
+    /// with a routing key of your choice. The message is tagged with the \
+    /// `application/json` content type:
+    /// ```
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, RoutingKey};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let status = vec![("jobs", 3), ("tasks", 120)];
+    /// let routing_key = RoutingKey::new("status").expect("Invalid routing key");
+    /// channel.post_info_json(routing_key, &status).expect("Couldn't post status");
+    /// ```
+    fn post_info_json<T>(&mut self, routing_key: RoutingKey, value: &T) -> GenResult<()> where T: Serialize{
+        let exchange = "info-topic";
+        let properties = BasicProperties{ content_type: Some("application/json".to_string()), ..Default::default()};
+        let message = serde_json::to_vec(value)?;
+        self.publish(exchange, routing_key.as_str(), properties, message)
+    }
+
+    /// Post a message to a fanout exchange and collect every reply that \
+    /// arrives within the window, e.g. to find out which workers are alive. \
+    /// The replies are expected on the temporary queue named in the \
+    /// `reply_to` property of the message, which is deleted afterwards:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let replies = channel.scatter_gather("worker-discovery", b"who's there".to_vec(), Duration::from_secs(2)).expect("Couldn't ask the workers");
+    /// println!("{} workers answered", replies.len());
+    /// ```
+    fn scatter_gather<S>(&mut self, exchange: S, body: Vec<u8>, window: Duration) -> GenResult<Vec<Vec<u8>>> where S: Into<String>{
+        let exchange = exchange.into();
+        let reply_queue = self.declare_anonymous_queue()?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), reply_to: Some(reply_queue.clone()), ..Default::default()};
+        // Fanout exchanges ignore the routing key
+        self.publish(exchange.as_str(), "", properties, body)?;
+        let start = std::time::Instant::now();
+        let mut replies = Vec::new();
+        while start.elapsed() < window {
+            let before = replies.len();
+            // basic_get returns nothing once the queue is empty instead of blocking
+            replies.extend(self.channel().basic_get(reply_queue.as_str(), true).map(|result| result.body));
+            if replies.len() == before { std::thread::sleep(Duration::from_millis(10)); }
+        }
+        queue::delete(self.channel(), reply_queue.as_str())?;
+        Ok(replies)
+    }
+
+    /// Broadcast a message to every worker and wait until at least `min_acks` \
+    /// of them replied, so critical messages are known to have reached a \
+    /// quorum of the fleet and not just the broker. The message is posted to \
+    /// `worker-topic` with the routing key `worker.all`, which reaches every \
+    /// private worker queue (see `create_private_worker_queue()`). A worker \
+    /// acknowledges by publishing any message to the default exchange with \
+    /// the `reply_to` property as routing key. Returns the number of replies \
+    /// once `min_acks` arrived or the timeout elapsed, whatever happens \
+    /// first, so fewer than `min_acks` means the quorum wasn't reached:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// # use std::time::Duration;
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// let acks = channel.broadcast_quorum(b"reload-config".to_vec(), 3, Duration::from_secs(5)).expect("Couldn't broadcast");
+    /// if acks < 3 {
+    ///     println!("Warning: Only {} workers confirmed the reload", acks);
+    /// }
+    /// ```
+    /// A worker answers like this:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ, Delivery};
+    /// # fn example(channel: &mut Channel, delivery: &Delivery){
+    /// if let Some(ref reply_to) = delivery.properties.reply_to {
+    ///     channel.publish_raw("", reply_to.as_str(), Default::default(), "ok").expect("Couldn't acknowledge");
+    /// }
+    /// # }
+    /// # fn main(){}
+    /// ```
+    fn broadcast_quorum(&mut self, body: Vec<u8>, min_acks: usize, timeout: Duration) -> GenResult<usize>{
+        let reply_queue = self.declare_anonymous_queue()?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), reply_to: Some(reply_queue.clone()), ..Default::default()};
+        self.publish("worker-topic", routing::WORKER_BROADCAST_KEY, properties, body)?;
+        let start = std::time::Instant::now();
+        let mut acks = 0;
+        while acks < min_acks && start.elapsed() < timeout {
+            let before = acks;
+            // basic_get returns nothing once the queue is empty instead of blocking
+            acks += self.channel().basic_get(reply_queue.as_str(), true).count();
+            if acks == before { std::thread::sleep(Duration::from_millis(10)); }
+        }
+        queue::delete(self.channel(), reply_queue.as_str())?;
+        Ok(acks)
+    }
+
+    /// Check the whole publish, route and consume path against the broker: \
+    /// a message is posted to a temporary exclusive queue, fetched back and \
+    /// compared, then the queue is deleted again. Meant to be called once at \
+    /// startup, so broker, permission or topology problems show up before \
+    /// real traffic does:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.self_test().expect("Self test failed");
+    /// ```
+    fn self_test(&mut self) -> GenResult<()>{
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.subsec_nanos();
+        let message = format!("bender-mq self test {}", nanos).into_bytes();
+        let queue = self.declare_anonymous_queue()?;
+        let properties = BasicProperties{ content_type: Some("text".to_string()), ..Default::default()};
+        // The default exchange routes to the queue with the same name
+        self.publish("", queue.as_str(), properties, message.clone())?;
+        let start = std::time::Instant::now();
+        let mut received = None;
+        while received.is_none() && start.elapsed() < std::time::Duration::from_secs(5) {
+            received = self.channel().basic_get(queue.as_str(), true).next().map(|result| result.body);
+            if received.is_none() { std::thread::sleep(std::time::Duration::from_millis(50)); }
+        }
+        queue::delete(self.channel(), queue.as_str())?;
+        match received {
+            Some(ref body) if body == &message => Ok(()),
+            Some(body) => Err(From::from(format!("Self test failed: expected {:?} but got {:?} from {}", String::from_utf8_lossy(&message), String::from_utf8_lossy(&body), queue))),
+            None => Err(From::from(format!("Self test failed: the message never arrived in {}", queue)))
+        }
+    }
+
+    /// Make sure this user may publish to every exchange the post methods \
+    /// use (`job` via the default exchange, `info-topic`, the \
+    /// `work_exchange()` and `worker-topic`), so a misconfigured user is \
+    /// noticed at startup instead of when the first job is posted. An \
+    /// expired message nobody receives is published to each of them. \
+    /// Missing permissions are returned as a `BenderMqError::AccessRefused` \
+    /// error, a missing exchange as `BenderMqError::ExchangeNotDeclared`. \
+    /// In both cases the broker closed the channel, so the connection has \
+    /// to be opened again:
+    /// ```no_run
+    /// # extern crate bender_mq;
+    /// # use bender_mq::{Channel, BenderMQ};
+    /// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+    /// channel.declare_topic_exchange().expect("Declaration of topic exchange failed");
+    /// channel.declare_work_exchange().expect("Declaration of work exchange failed");
+    /// channel.declare_worker_exchange().expect("Declaration of worker exchange failed");
+    /// channel.check_permissions().expect("Missing permissions");
+    /// ```
+    fn check_permissions(&mut self) -> GenResult<()>{
+        let work_exchange = self.work_exchange();
+        for exchange in &["", "info-topic", work_exchange.as_str(), "worker-topic"] {
+            permissions::check_publish(self.channel(), exchange)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Subscribe the consumer to the queue with the given arguments and \
+/// remember it with `Hooks::record_consumer()`. Returns the consumer tag
+fn subscribe<B, C>(mq: &mut B, consumer: C, queue: String, arguments: Table, no_ack: bool) -> GenResult<String> where B: BenderMQ + ?Sized, C: Consumer + 'static{
+    // callback, queue, consumer_tag, no_local, no_ack, exclusive, nowait, arguments
+    let consumer_tag = mq.channel().basic_consume(consumer, queue.clone(), "".to_string(), false, no_ack, false, false, arguments.clone()).map_err(BenderMqError::from)?;
+    mq.record_consumer(consumer_tag.as_str(), queue.as_str(), &arguments, no_ack, false);
+    Ok(consumer_tag)
+}
+
+
+/// A bare Channel has nowhere to keep any bookkeeping
+impl Hooks for Channel{
+    /// Publish in confirm mode, counting the delivery tags from 1
+    fn publish_confirmed(&mut self, routing_key: &str, messages: Vec<(BasicProperties, Vec<u8>)>) -> GenResult<()>{
+        queue::publish_confirmed(self, routing_key, messages)
+    }
+}
+
+
+impl BenderMQ for Channel{
+    /// Open a AMPQ session and return a channel.
+    fn open_channel<S>(url: S) -> GenResult<Self> where S: Into<String>{
+        let options = session::options_from_url(url.into().as_str())?;
+        let mut session = Session::new(options).map_err(BenderMqError::from)?;
+        let channel = session.open_channel(1)?;
+        Ok(channel)
+    }
+
+    /// Open a AMPQ session and return a channel to the default URK specified in\
+    /// the config. A missing or broken config is returned as an error.
+    fn open_default_channel() -> GenResult<Self>{
+        let url = session::url_from_config()?;
+        Channel::open_channel(url)
+    }
+
+    /// Open a AMPQ session and return a channel, retrying with backoff
+    fn open_channel_with_backoff<S>(url: S, backoff: &BackoffConfig, attempts: u32) -> GenResult<Self> where S: Into<String>{
+        let url = url.into();
+        let options = session::options_from_url(url.as_str())?;
+        let mut attempt = 0;
+        loop {
+            let result = Session::new(session::copy_options(&options)).and_then(|mut session| session.open_channel(1));
+            match result {
+                Ok(channel) => return Ok(channel),
+                Err(err) => {
+                    attempt += 1;
+                    if attempt >= attempts { return Err(Box::new(BenderMqError::from(err))); }
+                    let delay = backoff.delay(attempt - 1);
+                    println!("Warning: Couldn't connect to {} ({}), retrying in {:?}", url, err, delay);
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+    }
+
+
+    /// A Channel is its own channel
+    fn channel(&mut self) -> &mut Channel{
+        self
+    }
+}
@@ -0,0 +1,62 @@
+//! Histograms for the durations the crate measures, in the shape Prometheus \
+//! expects (cumulative buckets plus count and sum), so they can be exported \
+//! with whatever metrics library the service uses:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::Histogram;
+//! # use std::time::Duration;
+//! let mut histogram = Histogram::default();
+//! histogram.observe(Duration::from_millis(3));
+//! histogram.observe(Duration::from_millis(40));
+//! assert_eq!(histogram.count(), 2);
+//! assert_eq!(histogram.buckets()[2], (Duration::from_millis(5), 1));
+//! ```
+
+use std::time::Duration;
+
+
+/// Upper bounds of the buckets in milliseconds
+const BUCKETS_MS: [u64; 13] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+
+/// Counts durations into buckets with fixed upper bounds
+#[derive(Debug, Clone, Default)]
+pub struct Histogram{
+    counts: [u64; 13],
+    count: u64,
+    sum: Duration
+}
+
+
+impl Histogram{
+    /// Record a duration
+    pub fn observe(&mut self, duration: Duration){
+        let millis = duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
+        if let Some(index) = BUCKETS_MS.iter().position(|&bound| millis <= bound) {
+            self.counts[index] += 1;
+        }
+        self.count += 1;
+        self.sum += duration;
+    }
+
+    /// The number of recorded durations
+    pub fn count(&self) -> u64{
+        self.count
+    }
+
+    /// The sum of all recorded durations
+    pub fn sum(&self) -> Duration{
+        self.sum
+    }
+
+    /// The upper bound of each bucket together with the number of recorded \
+    /// durations up to that bound. Durations above the last bound are only \
+    /// part of `count()`
+    pub fn buckets(&self) -> Vec<(Duration, u64)>{
+        let mut cumulative = 0;
+        BUCKETS_MS.iter().zip(self.counts.iter()).map(|(&bound, &count)| {
+            cumulative += count;
+            (Duration::from_millis(bound), cumulative)
+        }).collect()
+    }
+}
@@ -0,0 +1,26 @@
+//! Flags that are sent along with every published message
+
+/// The transport flags of `basic.publish`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublishOptions{
+    /// Return the message to the publisher if it can't be routed to any queue
+    pub mandatory: bool,
+    /// Return the message if no consumer can take it right away. Deprecated \
+    /// and unsupported by RabbitMQ 3 and later, which close the channel when \
+    /// it is set
+    pub immediate: bool,
+    /// Attach the SHA-256 of the body as the `content-hash` header, so \
+    /// consumers can detect a corrupted body (see `consume_verified()`)
+    pub content_hash: bool
+}
+
+
+impl Default for PublishOptions{
+    fn default() -> Self{
+        PublishOptions{
+            mandatory: true,
+            immediate: false,
+            content_hash: false
+        }
+    }
+}
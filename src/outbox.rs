@@ -0,0 +1,266 @@
+//! Buffering of messages while the broker can't be reached. Messages leave \
+//! the buffer strictly in the order they entered it: a flush always starts \
+//! with the oldest message and stops at the first one that can't be \
+//! published, so nothing published later can overtake it. This matters for \
+//! tasks, where a frame may depend on the one posted before it.
+
+use std::collections::VecDeque;
+use amqp::protocol::basic::BasicProperties;
+use backoff::BackoffConfig;
+use connection::Connection;
+use error::BenderMqError;
+use {BenderMQ, GenResult};
+
+
+/// A message waiting in an `Outbox`
+#[derive(Debug, Clone)]
+pub struct OutboxMessage{
+    /// The exchange to publish to
+    pub exchange: String,
+    /// The routing key to publish with
+    pub routing_key: String,
+    /// The properties to publish with
+    pub properties: BasicProperties,
+    /// The message body
+    pub body: Vec<u8>
+}
+
+
+/// What a `ReconnectingPublisher` publishes through: anything that can \
+/// publish a message and open itself again after publishing failed. \
+/// `Connection` is the one to use with a broker
+pub trait Reconnectable{
+    /// Publish the message
+    fn publish_message(&mut self, message: &OutboxMessage) -> GenResult<()>;
+
+    /// Get a working connection again, retrying with the backoff for at \
+    /// most `attempts` attempts
+    fn reconnect(&mut self, backoff: &BackoffConfig, attempts: u32) -> GenResult<()>;
+}
+
+
+impl Reconnectable for Connection{
+    /// Publish the message with `publish()`
+    fn publish_message(&mut self, message: &OutboxMessage) -> GenResult<()>{
+        self.publish(message.exchange.as_str(), message.routing_key.as_str(), message.properties.clone(), message.body.clone())
+    }
+
+    /// Replace the session and channel (see `Connection::reconnect()`)
+    fn reconnect(&mut self, backoff: &BackoffConfig, attempts: u32) -> GenResult<()>{
+        Connection::reconnect(self, backoff, attempts)
+    }
+}
+
+
+/// First in, first out buffer for messages that couldn't be published yet. \
+/// New messages always queue behind the ones already waiting:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::{Outbox, OutboxMessage};
+/// let message = |body: &str| OutboxMessage{
+///     exchange: "work".to_string(),
+///     routing_key: "task".to_string(),
+///     properties: Default::default(),
+///     body: body.as_bytes().to_vec()
+/// };
+/// let mut broker: Vec<Vec<u8>> = Vec::new();
+/// let mut outbox = Outbox::default();
+///
+/// // The broker is gone, nothing gets through
+/// outbox.push(message("1"));
+/// outbox.push(message("2"));
+/// assert!(outbox.flush(|_| Err(From::from("connection lost"))).is_err());
+/// outbox.push(message("3"));
+/// assert_eq!(outbox.len(), 3);
+///
+/// // It comes back after accepting a single message
+/// let mut accepted = 0;
+/// let result = outbox.flush(|m|{
+///     if accepted == 1 { return Err(From::from("connection lost")); }
+///     accepted += 1;
+///     broker.push(m.body.clone());
+///     Ok(())
+/// });
+/// assert!(result.is_err());
+/// outbox.push(message("4"));
+///
+/// // And stays
+/// outbox.flush(|m|{ broker.push(m.body.clone()); Ok(()) }).expect("Flush failed");
+/// assert!(outbox.is_empty());
+/// assert_eq!(broker, vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec(), b"4".to_vec()]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Outbox{
+    pending: VecDeque<OutboxMessage>
+}
+
+
+impl Outbox{
+    /// Queue a message behind the ones already waiting
+    pub fn push(&mut self, message: OutboxMessage){
+        self.pending.push_back(message);
+    }
+
+    /// The number of messages waiting
+    pub fn len(&self) -> usize{
+        self.pending.len()
+    }
+
+    /// True if no message is waiting
+    pub fn is_empty(&self) -> bool{
+        self.pending.is_empty()
+    }
+
+    /// Hand the waiting messages to `publish` oldest first. A message is \
+    /// removed once `publish` succeeded, the first error stops the flush \
+    /// and is returned with that message (and all after it) still waiting
+    pub fn flush<F>(&mut self, mut publish: F) -> GenResult<()> where F: FnMut(&OutboxMessage) -> GenResult<()>{
+        while let Some(message) = self.pending.pop_front() {
+            if let Err(err) = publish(&message) {
+                self.pending.push_front(message);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Publishes through a Connection (or any other `Reconnectable`) and \
+/// reconnects (see `Connection::reconnect()`) when publishing fails. Messages that couldn't \
+/// be published are kept in an `Outbox` and go out first, in their original \
+/// order, once the connection is back. Every later message queues behind \
+/// them, even if the connection came back in the meantime. \
+/// While the broker applies flow control (`BenderMqError::FlowBlocked`) or \
+/// doesn't answer in time the messages wait without reconnecting. If the \
+/// reconnect fails as well the error is returned and the messages keep \
+/// waiting for the next `publish()` or `flush()`. \
+/// Note that a message the old channel published but the broker didn't \
+/// confirm yet isn't sent again, so a disconnect may still lose the last few \
+/// messages before it:
+/// ```no_run
+/// # extern crate bender_mq;
+/// # use bender_mq::{Connection, BenderMQ, BackoffConfig, ReconnectingPublisher};
+/// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+/// let mut publisher = ReconnectingPublisher::new(&mut connection, BackoffConfig::default(), 10);
+/// for frame in 1..250 {
+///     let body = format!("{{\"frame\": {}}}", frame);
+///     if let Err(err) = publisher.publish("work", "task", Default::default(), body) {
+///         println!("Warning: {} messages are waiting for the broker: {}", publisher.pending(), err);
+///     }
+/// }
+/// publisher.flush().expect("Couldn't publish the remaining messages");
+/// ```
+/// Messages published while the connection is down wait behind the ones \
+/// that failed before them and reach the broker in order once it is back:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::{BackoffConfig, OutboxMessage, Reconnectable, ReconnectingPublisher};
+/// # use std::cell::RefCell;
+/// # use std::rc::Rc;
+/// struct FakeBroker{ up: bool, received: Vec<String> }
+/// struct FakeConnection(Rc<RefCell<FakeBroker>>);
+///
+/// impl Reconnectable for FakeConnection{
+///     fn publish_message(&mut self, message: &OutboxMessage) -> Result<(), Box<std::error::Error>>{
+///         let mut broker = self.0.borrow_mut();
+///         if !broker.up { return Err(From::from("connection lost")); }
+///         broker.received.push(String::from_utf8_lossy(&message.body).to_string());
+///         Ok(())
+///     }
+///
+///     fn reconnect(&mut self, _: &BackoffConfig, _: u32) -> Result<(), Box<std::error::Error>>{
+///         match self.0.borrow().up {
+///             true => Ok(()),
+///             false => Err(From::from("broker unreachable"))
+///         }
+///     }
+/// }
+///
+/// # fn main(){
+/// let broker = Rc::new(RefCell::new(FakeBroker{ up: true, received: Vec::new() }));
+/// let mut connection = FakeConnection(broker.clone());
+/// {
+///     let mut publisher = ReconnectingPublisher::new(&mut connection, BackoffConfig::default(), 1);
+///     publisher.publish("work", "task", Default::default(), "1").expect("Publishing failed");
+///
+///     broker.borrow_mut().up = false;
+///     assert!(publisher.publish("work", "task", Default::default(), "2").is_err());
+///     assert!(publisher.publish("work", "task", Default::default(), "3").is_err());
+///     assert_eq!(publisher.pending(), 2);
+///
+///     broker.borrow_mut().up = true;
+///     publisher.publish("work", "task", Default::default(), "4").expect("Publishing failed");
+///     assert_eq!(publisher.pending(), 0);
+/// }
+/// assert_eq!(broker.borrow().received, vec!["1", "2", "3", "4"]);
+/// # }
+/// ```
+pub struct ReconnectingPublisher<'a, C: 'a + Reconnectable>{
+    connection: &'a mut C,
+    outbox: Outbox,
+    backoff: BackoffConfig,
+    attempts: u32
+}
+
+
+impl<'a, C: Reconnectable> ReconnectingPublisher<'a, C>{
+    /// Publish through the given connection, reconnecting with the backoff \
+    /// for at most `attempts` attempts each time publishing fails
+    pub fn new(connection: &'a mut C, backoff: BackoffConfig, attempts: u32) -> Self{
+        ReconnectingPublisher{
+            connection,
+            outbox: Outbox::default(),
+            backoff,
+            attempts
+        }
+    }
+
+    /// The number of messages waiting for the broker
+    pub fn pending(&self) -> usize{
+        self.outbox.len()
+    }
+
+    /// Queue a message behind the waiting ones and publish them all
+    pub fn publish<S, U>(&mut self, exchange: S, routing_key: S, properties: BasicProperties, body: U) -> GenResult<()> where S: Into<String>, U: Into<Vec<u8>>{
+        self.outbox.push(OutboxMessage{
+            exchange: exchange.into(),
+            routing_key: routing_key.into(),
+            properties,
+            body: body.into()
+        });
+        self.flush()
+    }
+
+    /// Publish the waiting messages, reconnecting once if that fails
+    pub fn flush(&mut self) -> GenResult<()>{
+        let err = match self.publish_pending() {
+            Ok(()) => return Ok(()),
+            Err(err) => err
+        };
+        let waits = match err.downcast_ref::<BenderMqError>() {
+            Some(&BenderMqError::FlowBlocked) | Some(&BenderMqError::Timeout) => true,
+            _ => false
+        };
+        if waits { return Err(err); }
+        println!("Warning: Publishing failed ({}), reconnecting with {} messages waiting", err, self.outbox.len());
+        self.connection.reconnect(&self.backoff, self.attempts)?;
+        self.publish_pending()
+    }
+
+    /// Hand the waiting messages to the connection, oldest first
+    fn publish_pending(&mut self) -> GenResult<()>{
+        let connection = &mut *self.connection;
+        self.outbox.flush(|message| connection.publish_message(message))
+    }
+}
+
+
+impl<'a, C: Reconnectable> Drop for ReconnectingPublisher<'a, C>{
+    fn drop(&mut self){
+        if self.outbox.is_empty() { return; }
+        if let Err(err) = self.publish_pending() {
+            println!("Error: Dropping {} messages that couldn't be published: {}", self.outbox.len(), err);
+        }
+    }
+}
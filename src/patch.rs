@@ -0,0 +1,70 @@
+//! JSON merge patches (RFC 7396) for posting only what changed. A patch is \
+//! a json object holding every field that changed with its new value, \
+//! nested objects are patched field by field and `null` removes a field. \
+//! Arrays are always replaced as a whole. As `null` means removal, a field \
+//! that changes to `null` is removed from the patched state instead:
+//! ```
+//! # extern crate bender_mq;
+//! # extern crate serde_json;
+//! # use bender_mq::{apply_merge_patch, merge_diff};
+//! # fn main(){
+//! let previous: serde_json::Value = serde_json::from_str(r#"{"id": "1", "progress": 10, "tasks": {"a": "queued"}}"#).unwrap();
+//! let current: serde_json::Value = serde_json::from_str(r#"{"id": "1", "progress": 20, "tasks": {"a": "running"}}"#).unwrap();
+//! let patch = merge_diff(&previous, &current).expect("Something changed");
+//! assert_eq!(patch.to_string(), r#"{"progress":20,"tasks":{"a":"running"}}"#);
+//!
+//! let mut cached = previous.clone();
+//! apply_merge_patch(&mut cached, &patch);
+//! assert_eq!(cached, current);
+//! assert!(merge_diff(&current, &current).is_none());
+//! # }
+//! ```
+
+use serde_json::{Map, Value};
+
+
+/// The patch that turns `previous` into `current`, None if they are equal
+pub fn merge_diff(previous: &Value, current: &Value) -> Option<Value>{
+    if previous == current { return None; }
+    match (previous, current) {
+        (&Value::Object(ref previous), &Value::Object(ref current)) => {
+            let mut patch = Map::new();
+            for (key, value) in current {
+                let changed = match previous.get(key) {
+                    Some(old) => merge_diff(old, value),
+                    None => Some(value.clone())
+                };
+                if let Some(changed) = changed {
+                    patch.insert(key.clone(), changed);
+                }
+            }
+            for key in previous.keys().filter(|key| !current.contains_key(*key)) {
+                patch.insert(key.clone(), Value::Null);
+            }
+            Some(Value::Object(patch))
+        },
+        _ => Some(current.clone())
+    }
+}
+
+
+/// Apply a patch of `merge_diff()` to the cached state
+pub fn apply_merge_patch(target: &mut Value, patch: &Value){
+    let fields = match *patch {
+        Value::Object(ref fields) => fields,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() { *target = Value::Object(Map::new()); }
+    if let Value::Object(ref mut target) = *target {
+        for (key, value) in fields {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                apply_merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Checking write access to an exchange before the first real publish. A \
+//! publish that isn't allowed doesn't fail by itself, the broker closes the \
+//! channel afterwards. So a throwaway message is published and followed by \
+//! a harmless request: if the answer is a `channel.close` instead of the \
+//! reply, the publish was refused.
+
+use amqp::{AMQPError, Basic, Channel, Table, protocol};
+use amqp::protocol::basic::BasicProperties;
+use amq_proto::Method;
+use error::BenderMqError;
+use GenResult;
+
+
+/// The amqp reply code for a missing exchange
+const NOT_FOUND: u16 = 404;
+
+/// The amqp reply code for missing permissions
+const ACCESS_REFUSED: u16 = 403;
+
+/// No queue is bound with this key, so the throwaway message is dropped
+const CHECK_ROUTING_KEY: &str = "bender-mq.permission-check";
+
+/// Exists on every broker and can be declared passively by every user
+const PROBE_EXCHANGE: &str = "amq.direct";
+
+
+/// Publish an expired, unroutable message to the exchange and make sure the \
+/// broker accepted it. The channel is closed by the broker if it didn't
+pub fn check_publish(channel: &mut Channel, exchange: &str) -> GenResult<()>{
+    let properties = BasicProperties{ expiration: Some("0".to_string()), ..Default::default() };
+    // exchange, routing_key, mandatory, immediate, properties, body
+    channel.basic_publish(exchange, CHECK_ROUTING_KEY, false, false, properties, Vec::new()).map_err(BenderMqError::from)?;
+    let declare = protocol::exchange::Declare{
+        ticket: 0,
+        exchange: PROBE_EXCHANGE.to_string(),
+        _type: "direct".to_string(),
+        passive: true,
+        durable: true,
+        auto_delete: false,
+        internal: false,
+        nowait: false,
+        arguments: Table::new()
+    };
+    let method_frame = channel.raw_rpc(&declare).map_err(BenderMqError::from)?;
+    match method_frame.method_name() {
+        "exchange.declare-ok" => Ok(()),
+        "channel.close" => {
+            let close = protocol::channel::Close::decode(method_frame).map_err(AMQPError::from).map_err(BenderMqError::from)?;
+            let _ = channel.send_method_frame(&protocol::channel::CloseOk);
+            match close.reply_code {
+                ACCESS_REFUSED => Err(Box::new(BenderMqError::AccessRefused(exchange.to_string(), close.reply_text))),
+                NOT_FOUND => Err(Box::new(BenderMqError::ExchangeNotDeclared(exchange.to_string()))),
+                _ => Err(From::from(format!("The broker closed the channel after publishing to {}: {}", exchange, close.reply_text)))
+            }
+        },
+        name => Err(From::from(format!("Unexpected method frame: {}, expected: exchange.declare-ok", name)))
+    }
+}
@@ -0,0 +1,87 @@
+//! Serialization profiles for consumers that expect a different wire format \
+//! than `Job::serialize()` and `Task::serialize()` produce. The profile is \
+//! applied to the serialized json right before it is published, so the rust \
+//! types stay as they are:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::SerializationProfile;
+//! # fn main(){
+//! let json = r#"{"parent_id":"1","frame":{"start_frame":1},"tasks":[{"task_id":"a"}]}"#.to_string();
+//! let camel = SerializationProfile::CamelCase.apply(json.clone()).unwrap();
+//! assert_eq!(camel, r#"{"frame":{"startFrame":1},"parentId":"1","tasks":[{"taskId":"a"}]}"#);
+//! assert_eq!(SerializationProfile::AsIs.apply(json.clone()).unwrap(), json);
+//! # }
+//! ```
+
+use serde_json::{Map, Value};
+use GenResult;
+
+
+/// How the field names of a serialized job or task are written on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerializationProfile{
+    /// Keep the field names as serialized (snake_case)
+    AsIs,
+    /// Rename every field from snake_case to camelCase, e.g. `parent_id` to \
+    /// `parentId`. This applies to nested objects as well, including objects \
+    /// that use ids as keys, which are left alone unless they contain an \
+    /// underscore
+    CamelCase
+}
+
+
+impl Default for SerializationProfile{
+    fn default() -> Self{
+        SerializationProfile::AsIs
+    }
+}
+
+
+impl SerializationProfile{
+    /// Apply the profile to serialized json. `AsIs` returns it untouched \
+    /// without parsing it
+    pub fn apply(&self, json: String) -> GenResult<String>{
+        match *self {
+            SerializationProfile::AsIs => Ok(json),
+            SerializationProfile::CamelCase => {
+                let value: Value = serde_json::from_str(json.as_str())?;
+                Ok(rename_fields(value, &camel_case).to_string())
+            }
+        }
+    }
+}
+
+
+/// Rename the keys of every object in the value, however deep it is nested
+fn rename_fields(value: Value, rename: &Fn(&str) -> String) -> Value{
+    match value {
+        Value::Object(fields) => {
+            let mut renamed = Map::new();
+            for (key, value) in fields {
+                renamed.insert(rename(key.as_str()), rename_fields(value, rename));
+            }
+            Value::Object(renamed)
+        },
+        Value::Array(values) => Value::Array(values.into_iter().map(|value| rename_fields(value, rename)).collect()),
+        value => value
+    }
+}
+
+
+/// `snake_case` to `camelCase`. Leading underscores are kept
+fn camel_case(name: &str) -> String{
+    let mut camel = String::with_capacity(name.len());
+    let mut upper_next = false;
+    for c in name.chars() {
+        if c == '_' && camel.chars().any(|c| c != '_') {
+            upper_next = true;
+        } else if upper_next {
+            camel.extend(c.to_uppercase());
+            upper_next = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    if upper_next { camel.push('_'); }
+    camel
+}
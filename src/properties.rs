@@ -0,0 +1,74 @@
+//! Helpers for the `BasicProperties` that are sent along with each message
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+use amqp::protocol::basic::BasicProperties;
+
+
+/// The `delivery_mode` of a message the broker writes to disk
+const PERSISTENT: u8 = 2;
+
+
+/// The `delivery_mode` property for a persistent message. Transient ones \
+/// leave it unset (which the broker treats as transient), so a default \
+/// from `Connection::set_default_properties()` still applies
+pub fn delivery_mode(persistent: bool) -> Option<u8>{
+    if persistent { Some(PERSISTENT) } else { None }
+}
+
+
+/// A new random id for the `correlation_id` property, 32 hex digits. The \
+/// time keeps ids of different processes apart even if their random parts \
+/// collide:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::new_correlation_id;
+/// assert_eq!(new_correlation_id().len(), 32);
+/// assert_ne!(new_correlation_id(), new_correlation_id());
+/// ```
+pub fn new_correlation_id() -> String{
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos())).unwrap_or(0);
+    format!("{:016x}{:016x}", nanos, random_u64())
+}
+
+
+/// A random number for ids, not fit for anything secret. std seeds the \
+/// `RandomState` keys from the OS once per thread and increments them for \
+/// every new `RandomState`, so hashing the same value gives a different \
+/// number on every call
+pub fn random_u64() -> u64{
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    hasher.finish()
+}
+
+
+/// Fill every property that isn't set with the value from the defaults. \
+/// Headers are merged key by key, again preferring the per message values.
+pub fn merge(properties: BasicProperties, defaults: &BasicProperties) -> BasicProperties{
+    let defaults = defaults.clone();
+    let headers = match (properties.headers, defaults.headers) {
+        (Some(headers), Some(mut default_headers)) => {
+            default_headers.extend(headers);
+            Some(default_headers)
+        },
+        (headers, default_headers) => headers.or(default_headers)
+    };
+    BasicProperties{
+        content_type: properties.content_type.or(defaults.content_type),
+        content_encoding: properties.content_encoding.or(defaults.content_encoding),
+        headers,
+        delivery_mode: properties.delivery_mode.or(defaults.delivery_mode),
+        priority: properties.priority.or(defaults.priority),
+        correlation_id: properties.correlation_id.or(defaults.correlation_id),
+        reply_to: properties.reply_to.or(defaults.reply_to),
+        expiration: properties.expiration.or(defaults.expiration),
+        message_id: properties.message_id.or(defaults.message_id),
+        timestamp: properties.timestamp.or(defaults.timestamp),
+        _type: properties._type.or(defaults._type),
+        user_id: properties.user_id.or(defaults.user_id),
+        app_id: properties.app_id.or(defaults.app_id),
+        cluster_id: properties.cluster_id.or(defaults.cluster_id)
+    }
+}
@@ -0,0 +1,25 @@
+//! Protobuf encoded tasks for consumers that don't speak json (e.g. the C++ \
+//! renderer). The protobuf message types come from the `.proto` definitions \
+//! shared with these consumers, generated with prost and converted from and \
+//! to a `Task` by the caller.
+
+use prost::Message;
+use GenResult;
+
+
+/// The content type protobuf encoded messages are tagged with
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+
+
+/// Encode a protobuf message into a message body
+pub fn encode<M>(message: &M) -> GenResult<Vec<u8>> where M: Message{
+    let mut body = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut body)?;
+    Ok(body)
+}
+
+
+/// Decode the body of a message that was posted with `post_task_proto()`
+pub fn decode_proto<M>(body: &[u8]) -> GenResult<M> where M: Message + Default{
+    Ok(M::decode(body)?)
+}
@@ -0,0 +1,243 @@
+//! Declaring queues with an error that explains why the broker refused
+
+use amqp::{AMQPError, Basic, Channel, Table, TableEntry, protocol};
+use amq_proto::Method;
+use std::time::Duration;
+use confirms::Confirms;
+use error::BenderMqError;
+use flow::{self, Intercepted};
+use {BenderMQ, GenResult};
+
+
+/// The amqp reply code for a declaration that doesn't match the existing one
+const PRECONDITION_FAILED: u16 = 406;
+
+/// The amqp reply code for a queue that doesn't exist
+const NOT_FOUND: u16 = 404;
+
+/// How many messages `move_messages()` holds in memory at once
+const MOVE_BATCH_SIZE: usize = 100;
+
+
+/// Declare a durable queue like `Channel::queue_declare()`. If the queue \
+/// already exists with other arguments the broker closes the channel with \
+/// `PRECONDITION_FAILED`, which is returned as a \
+/// `BenderMqError::QueueArgumentsMismatch` error
+pub fn declare(channel: &mut Channel, queue: &str, arguments: Table) -> GenResult<protocol::queue::DeclareOk>{
+    let declare = protocol::queue::Declare{
+        ticket: 0,
+        queue: queue.to_string(),
+        passive: false,
+        durable: true,
+        exclusive: false,
+        auto_delete: false,
+        nowait: false,
+        arguments
+    };
+    let method_frame = channel.raw_rpc(&declare).map_err(BenderMqError::from)?;
+    match method_frame.method_name() {
+        "queue.declare-ok" => Ok(protocol::queue::DeclareOk::decode(method_frame).map_err(AMQPError::from).map_err(BenderMqError::from)?),
+        "channel.close" => {
+            let close = protocol::channel::Close::decode(method_frame).map_err(AMQPError::from).map_err(BenderMqError::from)?;
+            // The channel is gone either way, this just lets the broker know we noticed
+            let _ = channel.send_method_frame(&protocol::channel::CloseOk);
+            if close.reply_code == PRECONDITION_FAILED {
+                Err(Box::new(BenderMqError::QueueArgumentsMismatch(queue.to_string(), close.reply_text)))
+            } else {
+                Err(From::from(format!("The broker closed the channel while declaring the queue {}: {}", queue, close.reply_text)))
+            }
+        },
+        name => Err(From::from(format!("Unexpected method frame: {}, expected: queue.declare-ok", name)))
+    }
+}
+
+
+/// Count the ready messages of a queue with a passive declaration. A queue \
+/// that doesn't exist is `None`. The broker closes the channel in that \
+/// case, so it can't be used afterwards
+pub fn message_count(channel: &mut Channel, queue: &str) -> GenResult<Option<u32>>{
+    let declare = protocol::queue::Declare{
+        ticket: 0,
+        queue: queue.to_string(),
+        passive: true,
+        durable: false,
+        exclusive: false,
+        auto_delete: false,
+        nowait: false,
+        arguments: Table::new()
+    };
+    let method_frame = channel.raw_rpc(&declare).map_err(BenderMqError::from)?;
+    match method_frame.method_name() {
+        "queue.declare-ok" => {
+            let declare_ok = protocol::queue::DeclareOk::decode(method_frame).map_err(AMQPError::from).map_err(BenderMqError::from)?;
+            Ok(Some(declare_ok.message_count))
+        },
+        "channel.close" => {
+            let close = protocol::channel::Close::decode(method_frame).map_err(AMQPError::from).map_err(BenderMqError::from)?;
+            let _ = channel.send_method_frame(&protocol::channel::CloseOk);
+            if close.reply_code == NOT_FOUND {
+                Ok(None)
+            } else {
+                Err(From::from(format!("The broker closed the channel while counting the messages of {}: {}", queue, close.reply_text)))
+            }
+        },
+        name => Err(From::from(format!("Unexpected method frame: {}, expected: queue.declare-ok", name)))
+    }
+}
+
+
+/// Arguments that make a queue drop messages older than the given age
+pub fn ttl_arguments(ttl: Option<Duration>) -> Table{
+    let mut arguments = Table::new();
+    if let Some(ttl) = ttl {
+        let millis = ttl.as_secs() * 1000 + u64::from(ttl.subsec_millis());
+        arguments.insert("x-message-ttl".to_string(), TableEntry::LongLongInt(millis as i64));
+    }
+    arguments
+}
+
+
+/// What the broker does with a new message when a queue is at its \
+/// `x-max-length`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow{
+    /// Drop the oldest message to make room for the new one
+    DropHead,
+    /// Refuse the new message. Publishers in confirm mode get a nack for it, \
+    /// which tells them to back off
+    RejectPublish
+}
+
+
+impl Overflow{
+    /// The value of the `x-overflow` argument
+    pub fn as_str(&self) -> &'static str{
+        match *self{
+            Overflow::DropHead => "drop-head",
+            Overflow::RejectPublish => "reject-publish"
+        }
+    }
+}
+
+
+/// Add the arguments that limit a queue to the given number of messages
+pub fn length_arguments(arguments: &mut Table, max_length: Option<u32>, overflow: Overflow){
+    if let Some(max_length) = max_length {
+        arguments.insert("x-max-length".to_string(), TableEntry::LongLongInt(i64::from(max_length)));
+        arguments.insert("x-overflow".to_string(), TableEntry::LongString(overflow.as_str().to_string()));
+    }
+}
+
+
+/// Add the argument that makes a queue lazy: the broker pages its messages \
+/// out to disk right away instead of keeping them in memory
+pub fn lazy_arguments(arguments: &mut Table, lazy: bool){
+    if lazy {
+        arguments.insert("x-queue-mode".to_string(), TableEntry::LongString("lazy".to_string()));
+    }
+}
+
+
+/// Delete a queue regardless of its messages and consumers. Returns the \
+/// number of messages that were deleted with it
+pub fn delete(channel: &mut Channel, queue: &str) -> GenResult<u32>{
+    let delete = protocol::queue::Delete{
+        ticket: 0,
+        queue: queue.to_string(),
+        if_unused: false,
+        if_empty: false,
+        nowait: false
+    };
+    let delete_ok: protocol::queue::DeleteOk = channel.rpc(&delete, "queue.delete-ok").map_err(BenderMqError::from)?;
+    Ok(delete_ok.message_count)
+}
+
+
+/// Delete a queue only if no messages are waiting in it. Returns false if \
+/// the broker refused because there are, in which case it closed the \
+/// channel
+pub fn delete_if_empty(channel: &mut Channel, queue: &str) -> GenResult<bool>{
+    let delete = protocol::queue::Delete{
+        ticket: 0,
+        queue: queue.to_string(),
+        if_unused: false,
+        if_empty: true,
+        nowait: false
+    };
+    let method_frame = channel.raw_rpc(&delete).map_err(BenderMqError::from)?;
+    match method_frame.method_name() {
+        "queue.delete-ok" => Ok(true),
+        "channel.close" => {
+            let close = protocol::channel::Close::decode(method_frame).map_err(AMQPError::from).map_err(BenderMqError::from)?;
+            let _ = channel.send_method_frame(&protocol::channel::CloseOk);
+            if close.reply_code == PRECONDITION_FAILED {
+                Ok(false)
+            } else {
+                Err(From::from(format!("The broker closed the channel while deleting the queue {}: {}", queue, close.reply_text)))
+            }
+        },
+        name => Err(From::from(format!("Unexpected method frame: {}, expected: queue.delete-ok", name)))
+    }
+}
+
+
+/// Move all messages from one queue into another one via the default \
+/// exchange, keeping their properties. Each batch is published in confirm \
+/// mode (see `Hooks::publish_confirmed()`) and acknowledged only after the \
+/// broker confirmed all of it, so a message is never gone from both queues. \
+/// Returns the number of moved messages
+pub fn move_messages<B>(mq: &mut B, from: &str, to: &str) -> GenResult<usize> where B: BenderMQ + ?Sized{
+    let mut moved = 0;
+    loop {
+        let batch: Vec<(u64, protocol::basic::BasicProperties, Vec<u8>)> = mq.channel().basic_get(from, false)
+            .take(MOVE_BATCH_SIZE)
+            .map(|result| (result.reply.delivery_tag, result.headers, result.body))
+            .collect();
+        let last_tag = match batch.last() {
+            Some(&(tag, _, _)) => tag,
+            None => return Ok(moved)
+        };
+        moved += batch.len();
+        mq.publish_confirmed(to, batch.into_iter().map(|(_, properties, body)| (properties, body)).collect())?;
+        // delivery_tag, multiple
+        mq.channel().basic_ack(last_tag, true).map_err(BenderMqError::from)?;
+    }
+}
+
+
+/// Delete a queue whose messages have been moved with `move_messages()`. \
+/// If anything arrived in the meantime the queue is kept and an error is \
+/// returned instead
+pub fn delete_moved(channel: &mut Channel, queue: &str) -> GenResult<()>{
+    if message_count(channel, queue)? != Some(0) || !delete_if_empty(channel, queue)? {
+        return Err(From::from(format!("The queue {} still holds messages after moving them, so it was kept", queue)));
+    }
+    Ok(())
+}
+
+
+/// Publish the messages to the default exchange in confirm mode and wait \
+/// until the broker confirmed all of them. The messages aren't mandatory, \
+/// as a returned message would end the `basic.get` iteration of \
+/// `move_messages()`. The tags are counted from 1, as a bare Channel \
+/// doesn't know whether it was in confirm mode before. A nack is an error
+pub fn publish_confirmed(channel: &mut Channel, routing_key: &str, messages: Vec<(protocol::basic::BasicProperties, Vec<u8>)>) -> GenResult<()>{
+    let _: protocol::confirm::SelectOk = channel.rpc(&protocol::confirm::Select{ nowait: false }, "confirm.select-ok").map_err(BenderMqError::from)?;
+    let mut confirms = Confirms::new();
+    for (properties, body) in messages {
+        // exchange, routing_key, mandatory, immediate, properties, body
+        channel.basic_publish("", routing_key, false, false, properties, body).map_err(BenderMqError::from)?;
+        confirms.published();
+    }
+    let tags = confirms.outstanding();
+    while confirms.pending() > 0 {
+        let frame = channel.read().map_err(BenderMqError::from)?;
+        if let Intercepted::Frame(frame) = flow::intercept(channel, frame)? {
+            confirms.record(&frame)?;
+        }
+    }
+    if tags.into_iter().any(|tag| confirms.take(tag) == Some(false)) {
+        return Err(From::from(format!("The broker didn't accept every message moved to {}", routing_key)));
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Pacing of publishes, so a burst submission of tens of thousands of tasks \
+//! doesn't saturate a broker that is shared with other tenants. The rate is \
+//! kept with a token bucket: it refills with `per_second` tokens per second \
+//! up to its `burst` capacity and every message takes one token. Short \
+//! bursts up to that capacity go out right away, anything beyond that at \
+//! the configured rate.
+
+use std::time::{Duration, Instant};
+use amqp::protocol::basic::BasicProperties;
+use bender_job::task::Task;
+use error::BenderMqError;
+use {BenderMQ, GenResult};
+
+
+/// A token bucket that allows `per_second` messages per second on average \
+/// and bursts of up to `burst` messages:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::TokenBucket;
+/// let mut bucket = TokenBucket::new(10, 2);
+/// assert!(bucket.try_take().is_ok());
+/// assert!(bucket.try_take().is_ok());
+/// // The burst is used up, the next token is ready in about 100ms
+/// let wait = bucket.try_take().unwrap_err();
+/// assert!(wait.as_secs() == 0 && wait.subsec_nanos() <= 100_000_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TokenBucket{
+    per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled: Instant
+}
+
+
+impl TokenBucket{
+    /// A full bucket. Both values are raised to 1 if they are 0
+    pub fn new(per_second: u32, burst: u32) -> Self{
+        let capacity = f64::from(burst.max(1));
+        TokenBucket{
+            per_second: f64::from(per_second.max(1)),
+            capacity,
+            tokens: capacity,
+            refilled: Instant::now()
+        }
+    }
+
+    /// Take a token if there is one, otherwise return how long it takes \
+    /// until the next one is available
+    pub fn try_take(&mut self) -> Result<(), Duration>{
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let seconds = (1.0 - self.tokens) / self.per_second;
+            Err(Duration::from_nanos((seconds * 1_000_000_000.0).ceil() as u64))
+        }
+    }
+
+    /// Take a token, sleeping until one is available
+    pub fn take(&mut self){
+        while let Err(wait) = self.try_take() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Add the tokens that accumulated since the last refill
+    fn refill(&mut self){
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.refilled = now;
+    }
+}
+
+
+/// Publishes through any `BenderMQ` implementor at no more than the \
+/// configured number of messages per second (see `TokenBucket`). By default \
+/// a publish over the rate blocks until it may go out. With `blocking(false)` \
+/// it returns a `BenderMqError::RateLimited` error holding how long to wait \
+/// instead, and nothing is published:
+/// ```no_run
+/// # extern crate bender_mq;
+/// # extern crate bender_job;
+/// # use bender_mq::{Channel, BenderMQ, RateLimitedPublisher};
+/// # fn example(tasks: &[bender_job::task::Task]){
+/// let mut channel = Channel::open_default_channel().expect("Couldn't aquire connection.");
+/// let mut publisher = RateLimitedPublisher::new(&mut channel, 500).burst(1000);
+/// for task in tasks {
+///     publisher.post_task(task).expect("Couldn't post task");
+/// }
+/// # }
+/// # fn main(){}
+/// ```
+pub struct RateLimitedPublisher<'a, B: 'a + BenderMQ>{
+    mq: &'a mut B,
+    bucket: TokenBucket,
+    per_second: u32,
+    blocking: bool
+}
+
+
+impl<'a, B: BenderMQ> RateLimitedPublisher<'a, B>{
+    /// Publish at most `per_second` messages per second, with a burst of \
+    /// one second worth of messages
+    pub fn new(mq: &'a mut B, per_second: u32) -> Self{
+        RateLimitedPublisher{
+            mq,
+            bucket: TokenBucket::new(per_second, per_second),
+            per_second,
+            blocking: true
+        }
+    }
+
+    /// Allow up to this many messages to go out at once before the rate \
+    /// applies
+    pub fn burst(mut self, burst: u32) -> Self{
+        self.bucket = TokenBucket::new(self.per_second, burst);
+        self
+    }
+
+    /// Whether a publish over the rate waits (the default) or returns a \
+    /// `BenderMqError::RateLimited` error
+    pub fn blocking(mut self, blocking: bool) -> Self{
+        self.blocking = blocking;
+        self
+    }
+
+    /// Post a task like `post_task()` once the rate allows it
+    pub fn post_task(&mut self, task: &Task) -> GenResult<String>{
+        self.acquire()?;
+        self.mq.post_task(task)
+    }
+
+    /// Publish a message like `publish()` once the rate allows it
+    pub fn publish(&mut self, exchange: &str, routing_key: &str, properties: BasicProperties, message: Vec<u8>) -> GenResult<()>{
+        self.acquire()?;
+        self.mq.publish(exchange, routing_key, properties, message)
+    }
+
+    /// Take a token, waiting for it or failing depending on `blocking()`
+    fn acquire(&mut self) -> GenResult<()>{
+        if self.blocking {
+            self.bucket.take();
+            Ok(())
+        } else {
+            self.bucket.try_take().map_err(|wait| From::from(BenderMqError::RateLimited(wait)))
+        }
+    }
+}
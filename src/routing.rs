@@ -0,0 +1,281 @@
+//! Routing keys for tasks on the `work` topic exchange. Tasks are routed as \
+//! `<job_id>.<engine>.<frame>`, so a queue bound to `*.cycles.#` only sees \
+//! the tasks that need the Cycles engine and one bound to `<job_id>.#` only \
+//! those of a single job.
+//!
+//! The fields are read from the serialized task, which is what gets posted \
+//! anyways. Deployments with other conventions plug in their own \
+//! `RoutingStrategy`.
+
+use std::fmt;
+use serde_json::Value;
+use bender_job::task::Task;
+use bender_job::Job;
+use GenResult;
+
+
+/// Word used for fields the task doesn't specify
+const UNKNOWN: &str = "unknown";
+
+/// The broker refuses longer routing keys
+const MAX_ROUTING_KEY_LENGTH: usize = 255;
+
+/// Routing key on the `worker-topic` exchange that reaches every private \
+/// worker queue
+pub const WORKER_BROADCAST_KEY: &str = "worker.all";
+
+/// Parameters of the 64 bit FNV-1a hash
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+
+/// A routing key that is safe to publish with: it contains none of the \
+/// wildcards `*` and `#` (which only mean something in bindings), no \
+/// whitespace or control characters, and fits into 255 bytes. The topic post \
+/// methods only take these, so a bad key fails where it is built instead of \
+/// silently misrouting:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::RoutingKey;
+/// assert!(RoutingKey::new("job.123.tasks").is_ok());
+/// assert!(RoutingKey::new("job.*.tasks").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RoutingKey(String);
+
+
+impl RoutingKey{
+    /// Check a routing key for publishing
+    pub fn new<S>(routing_key: S) -> GenResult<Self> where S: Into<String>{
+        let routing_key = routing_key.into();
+        if routing_key.len() > MAX_ROUTING_KEY_LENGTH {
+            return Err(From::from(format!("The routing key {} is longer than {} bytes", routing_key, MAX_ROUTING_KEY_LENGTH)));
+        }
+        match routing_key.chars().find(|&c| c == '*' || c == '#' || c.is_whitespace() || c.is_control()) {
+            Some(c) => Err(From::from(format!("The routing key {:?} contains {:?}, which can't be published with", routing_key, c))),
+            None => Ok(RoutingKey(routing_key))
+        }
+    }
+
+    /// The routing key as it is sent to the broker
+    pub fn as_str(&self) -> &str{
+        self.0.as_str()
+    }
+}
+
+
+impl fmt::Display for RoutingKey{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        write!(f, "{}", self.0)
+    }
+}
+
+
+/// Decides the routing keys `post_job_info()` and `post_task()` post with, \
+/// so a deployment can route by client, priority or whatever its queues are \
+/// bound to. The keys still have to pass `RoutingKey::new()`. To route the \
+/// job updates of each client under its own prefix:
+/// ```no_run
+/// # extern crate bender_mq;
+/// # extern crate bender_job;
+/// # use bender_mq::{Connection, BenderMQ, RoutingStrategy, task_routing_key};
+/// # use bender_job::Job;
+/// # use bender_job::task::Task;
+/// struct ByClient(String);
+///
+/// impl RoutingStrategy for ByClient{
+///     fn job_info_key(&self, job: &Job) -> String{
+///         format!("{}.{}", self.0, job.id())
+///     }
+///
+///     fn task_key(&self, task: &Task) -> String{
+///         task_routing_key(task)
+///     }
+/// }
+///
+/// let mut connection = Connection::open_default_channel().expect("Couldn't aquire connection.");
+/// connection.set_routing_strategy(ByClient("render-farm-2".to_string()));
+/// ```
+pub trait RoutingStrategy: Send{
+    /// The routing key a job update is posted to `info-topic` with
+    fn job_info_key(&self, job: &Job) -> String;
+
+    /// The routing key a task is posted to the work exchange with
+    fn task_key(&self, task: &Task) -> String;
+}
+
+
+/// The routing bender_mq uses unless told otherwise: job updates are routed \
+/// by the job id, tasks by `task_routing_key()`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultRoutingStrategy;
+
+
+impl RoutingStrategy for DefaultRoutingStrategy{
+    fn job_info_key(&self, job: &Job) -> String{
+        job.id()
+    }
+
+    fn task_key(&self, task: &Task) -> String{
+        task_routing_key(task)
+    }
+}
+
+
+/// Build the routing key `<job_id>.<engine>.<frame>` a task is posted with \
+/// by `post_task()`. Fields the task doesn't specify are replaced by \
+/// `unknown`:
+/// ```
+/// # extern crate bender_mq;
+/// # extern crate bender_job;
+/// # use bender_mq::task_routing_key;
+/// # fn example(task: &bender_job::task::Task){
+/// let key = task_routing_key(task);
+/// assert_eq!(key.split('.').count(), 3);
+/// # }
+/// # fn main(){}
+/// ```
+pub fn task_routing_key(task: &Task) -> String{
+    match task.serialize() {
+        Ok(json) => work_routing_key(json.as_str()),
+        Err(_) => work_routing_key("")
+    }
+}
+
+
+/// Build the routing key for a serialized task
+pub fn work_routing_key(task_json: &str) -> String{
+    let value: Value = serde_json::from_str(task_json).unwrap_or(Value::Null);
+    format!("{}.{}.{}", field(&value, "parent_id"), field(&value, "engine"), field(&value, "frame"))
+}
+
+
+/// Binding key for a queue that should receive all tasks of an engine
+pub fn engine_binding_key(engine: &str) -> String{
+    format!("*.{}.#", sanitize(engine))
+}
+
+
+/// What a worker can render. The routing key of a task only carries its \
+/// job, engine and frame, so only these can be bound on. Requirements the \
+/// key doesn't carry (e.g. a GPU) have to be checked by the worker when it \
+/// gets the task
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerCapabilities{
+    /// The render engines (e.g. `cycles`) the worker has installed
+    pub engines: Vec<String>
+}
+
+
+/// The binding keys on the `work` exchange that match exactly the tasks a \
+/// worker with these capabilities can render. They are built from the same \
+/// words as the routing keys of `post_task()`, so both sides can't drift \
+/// apart. A worker without any engine gets no keys:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::{WorkerCapabilities, capability_binding_keys, topic_matches};
+/// let capabilities = WorkerCapabilities{ engines: vec!["Cycles".to_string(), "eevee".to_string()] };
+/// let keys = capability_binding_keys(&capabilities);
+/// assert_eq!(keys, vec!["*.cycles.#", "*.eevee.#"]);
+/// assert!(keys.iter().any(|key| topic_matches("123.cycles.42", key)));
+/// ```
+pub fn capability_binding_keys(capabilities: &WorkerCapabilities) -> Vec<String>{
+    let mut keys: Vec<String> = capabilities.engines.iter()
+        .map(|engine| engine_binding_key(engine.as_str()))
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+
+/// Routing key `partition.<n>` for a task on the `work-partition` \
+/// exchange. All tasks of a job land in the same partition out of \
+/// `partitions`. The job id is hashed with FNV-1a, which gives the same \
+/// partition on every machine and Rust version
+pub fn partition_routing_key(task_json: &str, partitions: u32) -> String{
+    let value: Value = serde_json::from_str(task_json).unwrap_or(Value::Null);
+    let hash = field(&value, "parent_id").bytes()
+        .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME));
+    partition_binding_key((hash % u64::from(partitions.max(1))) as u32)
+}
+
+
+/// Binding key for a queue that should receive the tasks of a partition
+pub fn partition_binding_key(partition: u32) -> String{
+    format!("partition.{}", partition)
+}
+
+
+/// Returns true if a topic exchange would route a message with this routing \
+/// key to a queue bound with this pattern. As with the broker, `*` stands \
+/// for exactly one word and `#` for zero or more words. Handy to check a \
+/// binding without a broker:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::topic_matches;
+/// assert!(topic_matches("123.cycles.42", "*.cycles.#"));
+/// assert!(topic_matches("job.123", "job.123.#"));
+/// assert!(topic_matches("", "#"));
+/// assert!(!topic_matches("123.eevee.42", "*.cycles.#"));
+/// assert!(!topic_matches("job", "job.*"));
+/// ```
+pub fn topic_matches(routing_key: &str, binding_pattern: &str) -> bool{
+    let key = words(routing_key);
+    let pattern = words(binding_pattern);
+    // matched[i][j]: the first i words of the pattern match the first j of the key
+    let mut matched = vec![vec![false; key.len() + 1]; pattern.len() + 1];
+    matched[0][0] = true;
+    for i in 1..=pattern.len() {
+        for j in 0..=key.len() {
+            matched[i][j] = match pattern[i - 1] {
+                "#" => matched[i - 1][j] || (j > 0 && matched[i][j - 1]),
+                "*" => j > 0 && matched[i - 1][j - 1],
+                word => j > 0 && matched[i - 1][j - 1] && key[j - 1] == word
+            };
+        }
+    }
+    matched[pattern.len()][key.len()]
+}
+
+
+/// The words of a routing key or pattern. The broker treats an empty key as \
+/// no words at all (not as one empty word)
+fn words(key: &str) -> Vec<&str>{
+    if key.is_empty() { Vec::new() } else { key.split('.').collect() }
+}
+
+
+/// Routing key on the `worker-topic` exchange that only reaches this \
+/// process: `worker.<hostname>.<pid>`
+pub fn private_worker_routing_key() -> String{
+    let hostname = std::env::var("HOSTNAME").ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_default();
+    let hostname = if hostname.is_empty() { UNKNOWN.to_string() } else { sanitize(hostname.as_str()) };
+    format!("worker.{}.{}", hostname, std::process::id())
+}
+
+
+/// Routing key `worker.<id>.heartbeat` on the `worker-topic` exchange. The \
+/// id becomes a single word, so `worker.*.heartbeat` matches every worker
+pub fn heartbeat_routing_key(worker_id: &str) -> String{
+    format!("worker.{}.heartbeat", sanitize(worker_id))
+}
+
+
+/// Read a string or number field from the task as a single routing key word
+fn field(value: &Value, name: &str) -> String{
+    match value.get(name){
+        Some(&Value::String(ref s)) if !s.is_empty() => sanitize(s),
+        Some(&Value::Number(ref n)) => sanitize(n.to_string().as_str()),
+        _ => UNKNOWN.to_string()
+    }
+}
+
+
+/// Dots separate the words of a routing key, so they can't be part of a word
+fn sanitize(word: &str) -> String{
+    word.to_lowercase().replace('.', "_")
+}
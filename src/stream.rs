@@ -0,0 +1,216 @@
+//! RabbitMQ streams keep their messages after they have been consumed, so a \
+//! consumer can start reading at any point of the history instead of only \
+//! seeing messages that arrive after it subscribed.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use amqp::{Table, TableEntry};
+use GenResult;
+
+
+/// The name of the stream queue that receives everything posted to `info-topic`
+pub const INFO_STREAM: &str = "info-stream";
+
+/// How many unacknowledged messages a stream consumer may have. Streams \
+/// refuse consumers without a prefetch limit
+pub const STREAM_PREFETCH: u16 = 100;
+
+
+/// Where a stream consumer starts reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamOffset{
+    /// Start with the oldest message still in the stream
+    First,
+    /// Start with the last chunk of messages written to the stream
+    Last,
+    /// Only read messages that arrive after subscribing
+    Next,
+    /// Start at the message with this numeric offset
+    Offset(u64),
+    /// Start with the messages written at this unix timestamp (in seconds)
+    Timestamp(u64)
+}
+
+
+impl StreamOffset{
+    /// The value of the `x-stream-offset` consumer argument
+    pub fn to_table_entry(&self) -> TableEntry{
+        match *self {
+            StreamOffset::First => TableEntry::LongString("first".to_string()),
+            StreamOffset::Last => TableEntry::LongString("last".to_string()),
+            StreamOffset::Next => TableEntry::LongString("next".to_string()),
+            StreamOffset::Offset(offset) => TableEntry::LongLongInt(offset as i64),
+            StreamOffset::Timestamp(seconds) => TableEntry::Timestamp(seconds)
+        }
+    }
+}
+
+
+/// Arguments that declare a queue as a stream
+pub fn queue_arguments() -> Table{
+    let mut arguments = Table::new();
+    arguments.insert("x-queue-type".to_string(), TableEntry::LongString("stream".to_string()));
+    arguments
+}
+
+
+/// Arguments for a consumer that starts reading a stream at the given offset
+pub fn consumer_arguments(offset: StreamOffset) -> Table{
+    let mut arguments = Table::new();
+    arguments.insert("x-stream-offset".to_string(), offset.to_table_entry());
+    arguments
+}
+
+
+/// Where a stream consumer keeps the offset of the last message it handled, \
+/// so it can pick up there after a restart. RabbitMQ only tracks offsets \
+/// for clients of its stream protocol, over AMQP the consumer has to keep \
+/// them itself, e.g. in a file (see `FileOffsetStore`) or a database
+pub trait OffsetStore: Send{
+    /// The offset stored last, None if nothing has been stored yet
+    fn load(&mut self) -> GenResult<Option<u64>>;
+
+    /// Replace the stored offset
+    fn store(&mut self, offset: u64) -> GenResult<()>;
+}
+
+
+/// Keeps the offset as a plain number in a file
+#[derive(Debug, Clone)]
+pub struct FileOffsetStore{
+    path: PathBuf
+}
+
+
+impl FileOffsetStore{
+    /// Store the offset in the file at the given path, which is created on \
+    /// the first commit
+    pub fn new<P>(path: P) -> Self where P: Into<PathBuf>{
+        FileOffsetStore{ path: path.into() }
+    }
+}
+
+
+impl OffsetStore for FileOffsetStore{
+    fn load(&mut self) -> GenResult<Option<u64>>{
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Some(text.trim().parse()?)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Box::new(err))
+        }
+    }
+
+    fn store(&mut self, offset: u64) -> GenResult<()>{
+        // Written next to the file and renamed, so a crash can't leave half a number
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, offset.to_string())?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+
+/// Tracks the offsets a stream consumer handled and commits the last one to \
+/// an `OffsetStore` every `commit_every` messages and once it is dropped. \
+/// Messages at or before the last handled offset are recognized as seen, \
+/// so a consumer that was restarted before its last commit skips what it \
+/// handled already instead of processing it twice:
+/// ```
+/// # extern crate bender_mq;
+/// # use bender_mq::{OffsetStore, OffsetTracker, StreamOffset};
+/// # use std::sync::{Arc, Mutex};
+/// #[derive(Clone, Default)]
+/// struct Memory(Arc<Mutex<Option<u64>>>);
+///
+/// impl OffsetStore for Memory{
+///     fn load(&mut self) -> Result<Option<u64>, Box<std::error::Error>>{ Ok(*self.0.lock().unwrap()) }
+///     fn store(&mut self, offset: u64) -> Result<(), Box<std::error::Error>>{ *self.0.lock().unwrap() = Some(offset); Ok(()) }
+/// }
+///
+/// # fn main(){
+/// let memory = Memory::default();
+/// let mut tracker = OffsetTracker::new(memory.clone(), 2).unwrap();
+/// assert_eq!(tracker.resume_offset(StreamOffset::First), StreamOffset::First);
+/// for offset in 0..3 {
+///     assert!(tracker.is_new(offset));
+///     tracker.handled(offset).unwrap();
+/// }
+/// // Committed after every second message
+/// assert_eq!(*memory.0.lock().unwrap(), Some(1));
+/// drop(tracker);
+/// assert_eq!(*memory.0.lock().unwrap(), Some(2));
+///
+/// let tracker = OffsetTracker::new(memory.clone(), 2).unwrap();
+/// assert_eq!(tracker.resume_offset(StreamOffset::First), StreamOffset::Offset(3));
+/// assert!(!tracker.is_new(2));
+/// # }
+/// ```
+pub struct OffsetTracker<O> where O: OffsetStore{
+    store: O,
+    commit_every: u64,
+    last: Option<u64>,
+    uncommitted: u64
+}
+
+
+impl<O> OffsetTracker<O> where O: OffsetStore{
+    /// Load the offset committed last from the store. `commit_every` is \
+    /// raised to 1 if it is 0
+    pub fn new(mut store: O, commit_every: u64) -> GenResult<Self>{
+        let last = store.load()?;
+        Ok(OffsetTracker{
+            store,
+            commit_every: commit_every.max(1),
+            last,
+            uncommitted: 0
+        })
+    }
+
+    /// Where to start reading: right after the last handled message, or \
+    /// at `default` if nothing has been handled yet
+    pub fn resume_offset(&self, default: StreamOffset) -> StreamOffset{
+        match self.last {
+            Some(last) => StreamOffset::Offset(last + 1),
+            None => default
+        }
+    }
+
+    /// False if the message at this offset has been handled already
+    pub fn is_new(&self, offset: u64) -> bool{
+        match self.last {
+            Some(last) => offset > last,
+            None => true
+        }
+    }
+
+    /// Mark the message at this offset as handled and commit if enough \
+    /// messages have been handled since the last commit
+    pub fn handled(&mut self, offset: u64) -> GenResult<()>{
+        self.last = Some(offset);
+        self.uncommitted += 1;
+        if self.uncommitted >= self.commit_every {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Store the last handled offset right away
+    pub fn commit(&mut self) -> GenResult<()>{
+        if self.uncommitted == 0 { return Ok(()); }
+        if let Some(last) = self.last {
+            self.store.store(last)?;
+        }
+        self.uncommitted = 0;
+        Ok(())
+    }
+}
+
+
+impl<O> Drop for OffsetTracker<O> where O: OffsetStore{
+    fn drop(&mut self){
+        if let Err(err) = self.commit() {
+            println!("Error: Couldn't commit the stream offset {:?}: {}", self.last, err);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Counting how many messages a consumer handles per second. A `Throughput` \
+//! is a cheap handle to shared counters: one clone goes into the consumer, \
+//! the others can be read from anywhere (e.g. a thread that logs or exports \
+//! them):
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::Throughput;
+//! # use std::time::Duration;
+//! let throughput = Throughput::new(Duration::from_secs(60));
+//! throughput.record();
+//! throughput.record();
+//! assert_eq!(throughput.total(), 2);
+//! assert!(throughput.throughput() > 0.0);
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// Rolling count of handled messages over a time window plus a total
+#[derive(Debug, Clone)]
+pub struct Throughput{
+    inner: Arc<Mutex<Counters>>
+}
+
+
+#[derive(Debug)]
+struct Counters{
+    window: Duration,
+    handled: VecDeque<Instant>,
+    total: u64
+}
+
+
+impl Counters{
+    /// Forget the messages that left the window
+    fn prune(&mut self, now: Instant){
+        while let Some(&oldest) = self.handled.front() {
+            if now.duration_since(oldest) <= self.window { break; }
+            self.handled.pop_front();
+        }
+    }
+}
+
+
+impl Throughput{
+    /// Count over the given window, e.g. the last minute
+    pub fn new(window: Duration) -> Self{
+        Throughput{
+            inner: Arc::new(Mutex::new(Counters{ window, handled: VecDeque::new(), total: 0 }))
+        }
+    }
+
+    /// Count a handled message
+    pub fn record(&self){
+        if let Ok(mut counters) = self.inner.lock() {
+            let now = Instant::now();
+            counters.prune(now);
+            counters.handled.push_back(now);
+            counters.total += 1;
+        }
+    }
+
+    /// Messages per second over the window
+    pub fn throughput(&self) -> f64{
+        match self.inner.lock() {
+            Ok(mut counters) => {
+                counters.prune(Instant::now());
+                let seconds = counters.window.as_secs() as f64 + f64::from(counters.window.subsec_nanos()) / 1e9;
+                if seconds > 0.0 { counters.handled.len() as f64 / seconds } else { 0.0 }
+            },
+            Err(_) => 0.0
+        }
+    }
+
+    /// All messages counted since this was created
+    pub fn total(&self) -> u64{
+        self.inner.lock().map(|counters| counters.total).unwrap_or(0)
+    }
+}
@@ -0,0 +1,115 @@
+//! A machine readable description of the exchanges, queues and bindings the \
+//! declare methods of the [BenderMQ](trait.BenderMQ.html) trait create, e.g. \
+//! to compare them against what the management API reports:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::intended_topology;
+//! let topology = intended_topology();
+//! let work = topology.exchanges.iter().find(|exchange| exchange.name == "work").unwrap();
+//! assert_eq!(work.kind, "topic");
+//! ```
+//!
+//! Queues whose name or arguments are only known at runtime aren't part of \
+//! it: the engine and partition queues (`work.<engine>`, \
+//! `work.partition.<n>`), the private worker queues and the broker named ones. \
+//! The `work` queue is described without the optional ttl and length limit, \
+//! the `work-retry` queue without its delay.
+
+use amqp::{Table, TableEntry};
+use stream;
+
+
+/// An exchange with the flags it is declared with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeSpec{
+    /// The name of the exchange
+    pub name: String,
+    /// `direct`, `fanout`, `topic` or `headers`
+    pub kind: String,
+    /// True if the exchange survives a broker restart
+    pub durable: bool
+}
+
+
+/// A queue with the flags and arguments it is declared with
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueSpec{
+    /// The name of the queue
+    pub name: String,
+    /// True if the queue survives a broker restart
+    pub durable: bool,
+    /// The `x-` arguments (queue type, dead letter exchange, ...)
+    pub arguments: Table
+}
+
+
+/// A binding of a queue to an exchange
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingSpec{
+    /// The queue that receives the messages
+    pub queue: String,
+    /// The exchange the messages are posted to
+    pub exchange: String,
+    /// The binding key, which may contain the wildcards `*` and `#`
+    pub routing_key: String
+}
+
+
+/// Everything the declare methods create on the broker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topology{
+    /// Every exchange
+    pub exchanges: Vec<ExchangeSpec>,
+    /// Every queue with a fixed name
+    pub queues: Vec<QueueSpec>,
+    /// Every binding of these queues
+    pub bindings: Vec<BindingSpec>
+}
+
+
+/// Describe the topology the declare methods create
+pub fn intended_topology() -> Topology{
+    let exchanges = [
+        ("info-topic", "topic"),
+        ("job", "direct"),
+        ("work", "topic"),
+        ("worker-topic", "topic"),
+        ("dead-letter", "topic"),
+        ("work-retry", "topic"),
+        ("work-priority", "topic"),
+        ("work-partition", "direct")
+    ];
+    let mut retry_arguments = Table::new();
+    retry_arguments.insert("x-dead-letter-exchange".to_string(), TableEntry::LongString("work".to_string()));
+    let queues = vec![
+        ("info", Table::new()),
+        ("job", Table::new()),
+        ("work", Table::new()),
+        ("worker", Table::new()),
+        ("dead-letter", Table::new()),
+        ("work-retry", retry_arguments),
+        ("work-priority", Table::new()),
+        (stream::INFO_STREAM, stream::queue_arguments())
+    ];
+    // The job queue is only reached through the default exchange
+    let bindings = [
+        ("info", "info-topic", "#"),
+        ("work", "work", "#"),
+        ("worker", "worker-topic", "#"),
+        ("dead-letter", "dead-letter", "#"),
+        ("work-retry", "work-retry", "#"),
+        ("work-priority", "work-priority", "#"),
+        (stream::INFO_STREAM, "info-topic", "#")
+    ];
+    Topology{
+        exchanges: exchanges.iter()
+            .map(|&(name, kind)| ExchangeSpec{ name: name.to_string(), kind: kind.to_string(), durable: true })
+            .collect(),
+        queues: queues.into_iter()
+            .map(|(name, arguments)| QueueSpec{ name: name.to_string(), durable: true, arguments })
+            .collect(),
+        bindings: bindings.iter()
+            .map(|&(queue, exchange, routing_key)| BindingSpec{ queue: queue.to_string(), exchange: exchange.to_string(), routing_key: routing_key.to_string() })
+            .collect()
+    }
+}
@@ -0,0 +1,90 @@
+//! Trace context propagation through message headers in the W3C \
+//! `traceparent` format (`00-<trace id>-<span id>-<flags>`), which \
+//! OpenTelemetry understands on both ends. Convert from and to the span \
+//! context of your tracer with the public fields:
+//! ```
+//! # extern crate bender_mq;
+//! # use bender_mq::TraceContext;
+//! let context = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+//! assert_eq!(context.span_id, 0x00f0_67aa_0ba9_02b7);
+//! let child = context.child();
+//! assert_eq!(child.trace_id, context.trace_id);
+//! assert_ne!(child.span_id, context.span_id);
+//! ```
+
+use amqp::{Table, TableEntry};
+use properties;
+
+
+/// The header the trace context is sent in
+pub const TRACEPARENT: &str = "traceparent";
+
+
+/// Identifies the span a message was published or consumed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext{
+    /// The id shared by all spans of a trace
+    pub trace_id: u128,
+    /// The id of this span
+    pub span_id: u64,
+    /// Whether the trace is recorded
+    pub sampled: bool
+}
+
+
+impl TraceContext{
+    /// Start a new sampled trace
+    pub fn new_root() -> Self{
+        TraceContext{
+            trace_id: (u128::from(random_id()) << 64) | u128::from(random_id()),
+            span_id: random_id(),
+            sampled: true
+        }
+    }
+
+    /// A new span in the same trace, e.g. for handling a consumed message
+    pub fn child(&self) -> Self{
+        TraceContext{ span_id: random_id(), ..*self }
+    }
+
+    /// Format as `traceparent` header value
+    pub fn to_traceparent(&self) -> String{
+        format!("00-{:032x}-{:016x}-{:02x}", self.trace_id, self.span_id, self.sampled as u8)
+    }
+
+    /// Parse a `traceparent` header value
+    pub fn from_traceparent(value: &str) -> Option<Self>{
+        let parts: Vec<&str> = value.trim().split('-').collect();
+        if parts.len() != 4 || parts[0] != "00" || parts[1].len() != 32 || parts[2].len() != 16 {
+            return None;
+        }
+        let trace_id = u128::from_str_radix(parts[1], 16).ok()?;
+        let span_id = u64::from_str_radix(parts[2], 16).ok()?;
+        let flags = u8::from_str_radix(parts[3], 16).ok()?;
+        // All zero ids are invalid
+        if trace_id == 0 || span_id == 0 { return None; }
+        Some(TraceContext{ trace_id, span_id, sampled: flags & 1 == 1 })
+    }
+
+    /// Read the context from the headers of a message
+    pub fn from_headers(headers: &Table) -> Option<Self>{
+        match headers.get(TRACEPARENT) {
+            Some(&TableEntry::LongString(ref value)) => TraceContext::from_traceparent(value),
+            _ => None
+        }
+    }
+
+    /// Add the context to the headers of a message
+    pub fn inject(&self, headers: &mut Table){
+        headers.insert(TRACEPARENT.to_string(), TableEntry::LongString(self.to_traceparent()));
+    }
+}
+
+
+/// A random non zero id
+fn random_id() -> u64{
+    match properties::random_u64() {
+        0 => 1,
+        id => id
+    }
+}